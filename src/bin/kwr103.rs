@@ -1,6 +1,6 @@
 use clap::Parser;
 
-use kwr103::{cli, command::*, eth, usb, EthConnection, Kwr103, UsbConnection};
+use kwr103::{cli, command::*, TransportKind};
 
 #[derive(Parser, Debug)]
 #[clap(author, version, about, long_about = None)]
@@ -15,6 +15,15 @@ pub struct Kwr103Args {
     #[command(flatten)]
     pub usb: cli::UsbDetails,
 
+    #[command(flatten)]
+    pub output: cli::OutputArgs,
+
+    #[command(flatten)]
+    pub repeat: cli::RepeatArgs,
+
+    #[command(flatten)]
+    pub limits: cli::LimitArgs,
+
     #[clap(subcommand)]
     pub command: cli::Command,
 }
@@ -22,38 +31,10 @@ pub struct Kwr103Args {
 fn main() -> anyhow::Result<()> {
     let args = Kwr103Args::parse();
 
-    let mut kwr103: Kwr103 = match args.connection {
-        cli::Connection {
-            device: Some(dev),
-            ip: None,
-        } => UsbConnection::new(&dev, args.usb.baud, args.usb.id)?.into(),
-
-        cli::Connection {
-            device: None,
-            ip: Some(ip),
-        } => EthConnection::new((ip, args.eth.port))?.into(),
-
-        _ => {
-            let mut serial_devices = usb::find_devices(args.usb.baud, args.usb.id);
-            let mut ethernet_devices = eth::find_devices();
-            match (serial_devices.len(), ethernet_devices.len()) {
-                (0, 0) => {
-                    eprintln!("No devices found");
-                    std::process::exit(1);
-                }
-                (1, 0) => serial_devices.remove(0).open()?.into(),
-                (0, 1) => ethernet_devices.remove(0).open()?.into(),
-                (_, _) => {
-                    eprintln!(
-                        "Multiple device connections found.\n\
-                              Specify explicitely using `--device=<DEVICE>` or `--ip=<IP>`"
-                    );
-                    std::process::exit(1);
-                }
-            }
-        }
-    };
+    args.limits.check(&args.command)?;
+    let mut kwr103 = cli::resolve_connection(&args.connection, &args.usb, &args.eth)?;
 
+    let formatter = args.output.formatter();
     match args.command {
         cli::Command::Voltage { u } => {
             kwr103.command(Voltage(u))?;
@@ -64,15 +45,32 @@ fn main() -> anyhow::Result<()> {
         cli::Command::Output { switch } => {
             kwr103.command(Output(switch))?;
         }
-        cli::Command::Status => {
-            println!("{}", kwr103.query::<Status>()?)
-        }
-        cli::Command::Info => {
-            println!("{}", kwr103.query::<DeviceInfo>()?)
-        }
+        cli::Command::Status { mode } => args.repeat.run(|| {
+            println!("{}", cli::status(&mut kwr103, mode, formatter)?);
+            Ok(())
+        })?,
+        cli::Command::Info => args.repeat.run(|| {
+            println!("{}", formatter.format(&kwr103.query::<DeviceInfo>()?));
+            Ok(())
+        })?,
         cli::Command::Dhcp { switch } => {
             kwr103.command(Dhcp(switch))?;
         }
+        cli::Command::Dump => {
+            println!("{:#}", cli::dump(&mut kwr103))
+        }
+        cli::Command::ScanIds { range } => {
+            if kwr103.transport_kind() != TransportKind::Usb {
+                anyhow::bail!("Scanning device ids requires a USB connection");
+            }
+            let found = kwr103.scan_device_ids(range)?;
+            if found.is_empty() {
+                println!("No device ids responded");
+            }
+            for id in found {
+                println!("Device id {} responds", id);
+            }
+        }
     }
 
     Ok(())
@@ -1,5 +1,14 @@
+use std::fmt;
+use std::ops::RangeInclusive;
+use std::thread;
+use std::time::Duration;
+
 use crate::command as cmd;
+use crate::usb::BaudRate;
+use crate::{eth, usb, validate_device_id, EthConnection, Kwr103, TransactionError};
 use clap::{Args, Subcommand};
+use serde::Serialize;
+use serde_json::{json, Value};
 
 #[derive(Debug, Args)]
 #[group(required = false, multiple = false)]
@@ -16,8 +25,8 @@ pub struct Connection {
 #[derive(Debug, Args, Clone)]
 pub struct UsbDetails {
     /// Serial baud rate
-    #[clap(long, default_value_t = 115200)]
-    pub baud: u32,
+    #[clap(long, default_value_t = BaudRate::B115200)]
+    pub baud: BaudRate,
 
     /// Optional RS485 device ID
     #[clap(long)]
@@ -31,16 +40,212 @@ pub struct EthDetails {
     pub port: u16,
 }
 
+/// Connect to the power supply specified on the command line
+///
+/// An explicitly given `--device` or `--ip` is used as is. Otherwise serial and ethernet devices
+/// are discovered, which must yield exactly one device.
+pub fn resolve_connection(
+    connection: &Connection,
+    usb: &UsbDetails,
+    eth: &EthDetails,
+) -> Result<Kwr103, TransactionError> {
+    let discover = || (usb::find_devices(usb.baud, usb.id), eth::find_devices());
+    Ok(match select_connection(connection, usb, eth, discover)? {
+        Selection::Usb(details) => details.open()?.into(),
+        Selection::Eth(details) => details.open()?.into(),
+        Selection::EthAddress(ip, port) => {
+            EthConnection::with_connect_timeout((ip, port), Duration::from_secs(2))?.into()
+        }
+    })
+}
+
+#[derive(Debug)]
+enum Selection {
+    Usb(usb::ConnectionDetails),
+    Eth(eth::ConnectionDetails),
+    EthAddress(String, u16),
+}
+
+fn select_connection(
+    connection: &Connection,
+    usb: &UsbDetails,
+    eth: &EthDetails,
+    discover: impl FnOnce() -> (Vec<usb::ConnectionDetails>, Vec<eth::ConnectionDetails>),
+) -> Result<Selection, TransactionError> {
+    match connection {
+        Connection {
+            device: Some(dev),
+            ip: None,
+        } => Ok(Selection::Usb(usb::ConnectionDetails {
+            serial: dev.clone(),
+            baud_rate: usb.baud,
+            device_id: usb.id,
+        })),
+
+        Connection {
+            device: None,
+            ip: Some(ip),
+        } => Ok(Selection::EthAddress(ip.clone(), eth.port)),
+
+        _ => {
+            let (mut serial_devices, mut ethernet_devices) = discover();
+            match (serial_devices.len(), ethernet_devices.len()) {
+                (0, 0) => Err(TransactionError::InvalidConfiguration(
+                    "No devices found".to_string(),
+                )),
+                (1, 0) => Ok(Selection::Usb(serial_devices.remove(0))),
+                (0, 1) => Ok(Selection::Eth(ethernet_devices.remove(0))),
+                (_, _) => Err(TransactionError::InvalidConfiguration(
+                    "Multiple device connections found. \
+                     Specify explicitely using `--device=<DEVICE>` or `--ip=<IP>`"
+                        .to_string(),
+                )),
+            }
+        }
+    }
+}
+
+#[derive(Debug, Args, Clone)]
+#[group(required = false, multiple = false)]
+pub struct OutputArgs {
+    /// Print query results as JSON
+    #[clap(long)]
+    pub json: bool,
+
+    /// Print query results as CSV
+    #[clap(long)]
+    pub csv: bool,
+}
+
+impl OutputArgs {
+    /// The [`OutputFormatter`] selected by the command line flags
+    pub fn formatter(&self) -> OutputFormatter {
+        match (self.json, self.csv) {
+            (true, _) => OutputFormatter::Json,
+            (_, true) => OutputFormatter::Csv,
+            _ => OutputFormatter::Text,
+        }
+    }
+}
+
+#[derive(Debug, Args, Clone)]
+pub struct RepeatArgs {
+    /// Number of times to run query subcommands
+    #[clap(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+    pub repeat: u32,
+
+    /// Delay between repeated queries in milliseconds
+    #[clap(long, default_value_t = 0)]
+    pub repeat_delay_ms: u64,
+}
+
+impl RepeatArgs {
+    /// Run `query` the configured number of times, waiting for the configured delay in between
+    pub fn run(
+        &self,
+        mut query: impl FnMut() -> Result<(), TransactionError>,
+    ) -> Result<(), TransactionError> {
+        for n in 0..self.repeat {
+            if n > 0 {
+                thread::sleep(Duration::from_millis(self.repeat_delay_ms));
+            }
+            query()?;
+        }
+        Ok(())
+    }
+}
+
+/// Output format for the results of query subcommands
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum OutputFormatter {
+    /// Human readable text
+    #[default]
+    Text,
+    /// Single line JSON object
+    Json,
+    /// Header line of field names followed by a line of values
+    Csv,
+}
+
+impl OutputFormatter {
+    /// Format a query result `value`
+    pub fn format(&self, value: &(impl Serialize + fmt::Display)) -> String {
+        match self {
+            OutputFormatter::Text => value.to_string(),
+            OutputFormatter::Json => json!(value).to_string(),
+            OutputFormatter::Csv => to_csv(json!(value)),
+        }
+    }
+}
+
+fn to_csv(value: Value) -> String {
+    let cell = |value: &Value| match value {
+        Value::String(s) => s.clone(),
+        Value::Null => String::new(),
+        other => other.to_string(),
+    };
+    match value {
+        Value::Object(fields) => {
+            let header: Vec<&str> = fields.keys().map(String::as_str).collect();
+            let values: Vec<String> = fields.values().map(cell).collect();
+            format!("{}\n{}", header.join(","), values.join(","))
+        }
+        other => cell(&other),
+    }
+}
+
+#[derive(Debug, Args, Clone)]
+pub struct LimitArgs {
+    /// Maximum voltage accepted by the voltage subcommand
+    #[clap(long, default_value_t = cmd::Ratings::KWR103.max_voltage, value_parser = parse_setpoint)]
+    pub max_voltage: f32,
+
+    /// Maximum current accepted by the current subcommand
+    #[clap(long, default_value_t = cmd::Ratings::KWR103.max_current, value_parser = parse_setpoint)]
+    pub max_current: f32,
+}
+
+impl LimitArgs {
+    /// Check the setpoint of a voltage or current subcommand against the configured maximum
+    pub fn check(&self, command: &Command) -> Result<(), TransactionError> {
+        let exceeded = match *command {
+            Command::Voltage { u } if u > self.max_voltage => {
+                format!(
+                    "Voltage {} V exceeds --max-voltage {} V",
+                    u, self.max_voltage
+                )
+            }
+            Command::Current { i } if i > self.max_current => {
+                format!(
+                    "Current {} A exceeds --max-current {} A",
+                    i, self.max_current
+                )
+            }
+            _ => return Ok(()),
+        };
+        Err(TransactionError::InvalidConfiguration(exceeded))
+    }
+}
+
+/// Parse a voltage or current setpoint, rejecting negative and non-finite values
+pub fn parse_setpoint(s: &str) -> Result<f32, String> {
+    let value: f32 = s.parse().map_err(|_| format!("'{}' is not a number", s))?;
+    if !value.is_finite() || value < 0.0 {
+        return Err(format!("'{}' must be a non-negative number", s));
+    }
+    Ok(value)
+}
+
 #[derive(Debug, Subcommand)]
 pub enum Command {
     /// Set the output voltage
     Voltage {
-        #[clap(help = "Volts")]
+        #[clap(help = "Volts", value_parser = parse_setpoint)]
         u: f32,
     },
     /// Set the output current
     Current {
-        #[clap(help = "Ampere")]
+        #[clap(help = "Ampere", value_parser = parse_setpoint)]
         i: f32,
     },
     /// Turn power supply output 'on' or 'off'
@@ -49,7 +254,11 @@ pub enum Command {
         switch: cmd::Switch,
     },
     /// Show current output voltage and current
-    Status,
+    Status {
+        /// Also show the regulation mode (CV/CC), if supported by the device
+        #[clap(long)]
+        mode: bool,
+    },
     /// Show system information
     Info,
     /// Turn DHCP 'on' or 'off'
@@ -57,4 +266,416 @@ pub enum Command {
         #[clap(help = "on/off")]
         switch: cmd::Switch,
     },
+    /// Dump the complete device configuration as JSON
+    Dump,
+    /// Probe which RS485 device ids respond
+    ScanIds {
+        #[clap(help = "Inclusive id range, e.g. 1-10", value_parser = parse_id_range)]
+        range: RangeInclusive<u8>,
+    },
+}
+
+/// Parse an inclusive RS485 device id range like `1-10`, or a single id like `5`
+pub fn parse_id_range(range: &str) -> Result<RangeInclusive<u8>, String> {
+    let invalid = || format!("Invalid id range '{}'", range);
+    let (start, end) = range.split_once('-').unwrap_or((range, range));
+    let start: u8 = start.trim().parse().map_err(|_| invalid())?;
+    let end: u8 = end.trim().parse().map_err(|_| invalid())?;
+    validate_device_id(start).map_err(|err| err.to_string())?;
+    validate_device_id(end).map_err(|err| err.to_string())?;
+    if start > end {
+        return Err(invalid());
+    }
+    Ok(start..=end)
+}
+
+/// Query and format the output status, including the regulation mode if `with_mode` is set
+///
+/// The mode is omitted for devices not answering the status byte query sensibly.
+pub fn status(
+    kwr103: &mut Kwr103,
+    with_mode: bool,
+    formatter: OutputFormatter,
+) -> Result<String, TransactionError> {
+    if with_mode {
+        match kwr103.query::<cmd::OutputState>() {
+            Ok(state) => return Ok(formatter.format(&state)),
+            Err(TransactionError::ResponseError(_)) => {}
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(formatter.format(&kwr103.query::<cmd::Status>()?))
+}
+
+/// Query the complete device configuration as a JSON object
+///
+/// Fields failing to be queried are set to `null` and reported in the `errors` array instead.
+pub fn dump(kwr103: &mut Kwr103) -> Value {
+    let mut errors = Vec::new();
+    let mut field = |name: &str, result: Result<Value, TransactionError>| {
+        result.unwrap_or_else(|err| {
+            errors.push(json!({ "field": name, "error": err.to_string() }));
+            Value::Null
+        })
+    };
+
+    let voltage = field(
+        "voltage",
+        kwr103.query::<cmd::Voltage>().map(|v| json!(v.0)),
+    );
+    let current = field(
+        "current",
+        kwr103.query::<cmd::Current>().map(|i| json!(i.0)),
+    );
+    let output = field(
+        "output",
+        kwr103.query::<cmd::Output>().map(|o| json!(o.0.is_on())),
+    );
+    let ovp = field(
+        "ovp",
+        kwr103
+            .query::<cmd::OverVoltageProtection>()
+            .map(|ovp| json!(ovp.0)),
+    );
+    let ocp = field(
+        "ocp",
+        kwr103
+            .query::<cmd::OverCurrentProtection>()
+            .map(|ocp| json!(ocp.0)),
+    );
+    let info = field(
+        "info",
+        kwr103.query::<cmd::DeviceInfo>().map(|info| json!(info)),
+    );
+
+    json!({
+        "voltage": voltage,
+        "current": current,
+        "output": output,
+        "ovp": ovp,
+        "ocp": ocp,
+        "info": info,
+        "errors": errors,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockTransport;
+
+    const USB: UsbDetails = UsbDetails {
+        baud: BaudRate::B9600,
+        id: Some(3),
+    };
+    const ETH: EthDetails = EthDetails { port: 18190 };
+
+    fn connection(device: Option<&str>, ip: Option<&str>) -> Connection {
+        Connection {
+            device: device.map(String::from),
+            ip: ip.map(String::from),
+        }
+    }
+
+    fn discovered(
+        serial: &[&str],
+        ips: &[[u8; 4]],
+    ) -> (Vec<usb::ConnectionDetails>, Vec<eth::ConnectionDetails>) {
+        let usb = serial
+            .iter()
+            .map(|dev| usb::ConnectionDetails {
+                serial: dev.to_string(),
+                baud_rate: USB.baud,
+                device_id: USB.id,
+            })
+            .collect();
+        let eth = ips
+            .iter()
+            .map(|&ip| eth::ConnectionDetails {
+                ip: ip.into(),
+                port: ETH.port,
+            })
+            .collect();
+        (usb, eth)
+    }
+
+    #[test]
+    fn select_explicit_usb() {
+        let selection =
+            select_connection(&connection(Some("/dev/ttyACM3"), None), &USB, &ETH, || {
+                panic!("no discovery for explicit device")
+            });
+        assert!(matches!(
+            selection,
+            Ok(Selection::Usb(usb::ConnectionDetails { serial, baud_rate: BaudRate::B9600, device_id: Some(3) }))
+                if serial == "/dev/ttyACM3"
+        ));
+    }
+
+    #[test]
+    fn select_explicit_eth() {
+        let selection = select_connection(&connection(None, Some("10.0.0.7")), &USB, &ETH, || {
+            panic!("no discovery for explicit ip")
+        });
+        assert!(matches!(
+            selection,
+            Ok(Selection::EthAddress(ip, 18190)) if ip == "10.0.0.7"
+        ));
+    }
+
+    #[test]
+    fn select_single_discovered_device() {
+        let selection = select_connection(&connection(None, None), &USB, &ETH, || {
+            discovered(&["/dev/ttyACM0"], &[])
+        });
+        assert!(
+            matches!(selection, Ok(Selection::Usb(details)) if details.serial == "/dev/ttyACM0")
+        );
+
+        let selection = select_connection(&connection(None, None), &USB, &ETH, || {
+            discovered(&[], &[[192, 168, 1, 198]])
+        });
+        assert!(matches!(
+            selection,
+            Ok(Selection::Eth(details)) if details.ip == std::net::Ipv4Addr::new(192, 168, 1, 198)
+        ));
+    }
+
+    #[test]
+    fn select_fails_unless_single_device_discovered() {
+        let selection = select_connection(&connection(None, None), &USB, &ETH, || {
+            discovered(&["/dev/ttyACM0"], &[[192, 168, 1, 198]])
+        });
+        assert!(selection.is_err_and(|e| e.to_string().contains("Multiple device connections")));
+
+        let selection =
+            select_connection(&connection(None, None), &USB, &ETH, || discovered(&[], &[]));
+        assert!(selection.is_err_and(|e| e.to_string().contains("No devices found")));
+    }
+
+    #[test]
+    fn parse_setpoint_values() {
+        assert_eq!(parse_setpoint("42.0"), Ok(42.0));
+        assert_eq!(parse_setpoint("0"), Ok(0.0));
+        assert!(parse_setpoint("-1.5").is_err());
+        assert!(parse_setpoint("NaN").is_err());
+        assert!(parse_setpoint("inf").is_err());
+        assert!(parse_setpoint("42,0").is_err());
+    }
+
+    #[test]
+    fn limits_reject_out_of_range_setpoints() {
+        let limits = LimitArgs {
+            max_voltage: 60.0,
+            max_current: 15.0,
+        };
+        assert!(limits.check(&Command::Voltage { u: 42.0 }).is_ok());
+        assert!(limits.check(&Command::Voltage { u: 60.0 }).is_ok());
+        assert!(limits
+            .check(&Command::Voltage { u: 420.0 })
+            .is_err_and(|e| e.to_string().contains("--max-voltage")));
+        assert!(limits.check(&Command::Current { i: 1.5 }).is_ok());
+        assert!(limits
+            .check(&Command::Current { i: 15.5 })
+            .is_err_and(|e| e.to_string().contains("--max-current")));
+        assert!(limits.check(&Command::Status { mode: false }).is_ok());
+    }
+
+    #[test]
+    fn parse_valid_id_range() {
+        assert_eq!(parse_id_range("1-10").unwrap(), 1..=10);
+        assert_eq!(parse_id_range(" 3 - 7 ").unwrap(), 3..=7);
+        assert_eq!(parse_id_range("5").unwrap(), 5..=5);
+        assert_eq!(parse_id_range("1-99").unwrap(), 1..=99);
+    }
+
+    #[test]
+    fn parse_invalid_id_range() {
+        assert!(parse_id_range("0-10").is_err());
+        assert!(parse_id_range("1-100").is_err());
+        assert!(parse_id_range("10-1").is_err());
+        assert!(parse_id_range("1-").is_err());
+        assert!(parse_id_range("a-b").is_err());
+        assert!(parse_id_range("1-2-3").is_err());
+    }
+
+    fn status() -> cmd::Status {
+        cmd::Status {
+            power: cmd::Switch::On,
+            voltage: 12.0,
+            current: 0.5,
+        }
+    }
+
+    #[test]
+    fn format_status_as_text() {
+        assert_eq!(
+            OutputFormatter::Text.format(&status()),
+            "Output: On, Voltage[V]: 12.000, Current[A]: 0.500"
+        );
+    }
+
+    #[test]
+    fn format_status_as_json() {
+        assert_eq!(
+            OutputFormatter::Json.format(&status()),
+            r#"{"power":true,"voltage":12.0,"current":0.5}"#
+        );
+    }
+
+    #[test]
+    fn format_status_as_csv() {
+        assert_eq!(
+            OutputFormatter::Csv.format(&status()),
+            "power,voltage,current\ntrue,12.0,0.5"
+        );
+    }
+
+    #[test]
+    fn format_non_finite_status_as_csv() {
+        let status = cmd::Status {
+            voltage: f32::NAN,
+            ..status()
+        };
+        assert_eq!(
+            OutputFormatter::Csv.format(&status),
+            "power,voltage,current\ntrue,,0.5"
+        );
+    }
+
+    #[derive(clap::Parser)]
+    struct RepeatCli {
+        #[command(flatten)]
+        repeat: RepeatArgs,
+    }
+
+    #[test]
+    fn repeat_flags_parse() {
+        use clap::Parser;
+
+        let args = RepeatCli::try_parse_from(["kwr103"]).unwrap();
+        assert_eq!((args.repeat.repeat, args.repeat.repeat_delay_ms), (1, 0));
+
+        let args =
+            RepeatCli::try_parse_from(["kwr103", "--repeat", "3", "--repeat-delay-ms", "100"])
+                .unwrap();
+        assert_eq!((args.repeat.repeat, args.repeat.repeat_delay_ms), (3, 100));
+
+        assert!(RepeatCli::try_parse_from(["kwr103", "--repeat", "0"]).is_err());
+    }
+
+    #[test]
+    fn repeat_runs_query_n_times() {
+        let mock = MockTransport::default();
+        for _ in 0..3 {
+            mock.respond(b"1\n12.000\n0.500\n");
+        }
+        let mut kwr103 = Kwr103::from(mock.clone());
+
+        let repeat = RepeatArgs {
+            repeat: 3,
+            repeat_delay_ms: 1,
+        };
+        let mut lines = Vec::new();
+        repeat
+            .run(|| {
+                let status = kwr103.query::<cmd::Status>()?;
+                lines.push(OutputFormatter::Text.format(&status));
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(mock.sent().len(), 3);
+    }
+
+    #[test]
+    fn status_with_mode() {
+        let mock = MockTransport::default();
+        mock.respond(b"1\n1\n12.000\n0.500\n");
+        let mut kwr103 = Kwr103::from(mock.clone());
+
+        let text = super::status(&mut kwr103, true, OutputFormatter::Text).unwrap();
+        assert_eq!(
+            text,
+            "Output: On, Mode: CV, Voltage[V]: 12.000, Current[A]: 0.500"
+        );
+        assert_eq!(mock.sent(), vec![b"OUT?\nSTATUS?\nVOUT?\nIOUT?\n".to_vec()]);
+    }
+
+    #[test]
+    fn status_omits_unsupported_mode() {
+        let mock = MockTransport::default();
+        mock.respond(b"1\nERR\n12.000\n0.500\n");
+        mock.respond(b"1\n12.000\n0.500\n");
+        let mut kwr103 = Kwr103::from(mock.clone());
+
+        let text = super::status(&mut kwr103, true, OutputFormatter::Text).unwrap();
+        assert_eq!(text, "Output: On, Voltage[V]: 12.000, Current[A]: 0.500");
+        assert_eq!(mock.sent().len(), 2);
+    }
+
+    #[test]
+    fn output_args_select_formatter() {
+        let args = |json, csv| OutputArgs { json, csv };
+        assert_eq!(args(false, false).formatter(), OutputFormatter::Text);
+        assert_eq!(args(true, false).formatter(), OutputFormatter::Json);
+        assert_eq!(args(false, true).formatter(), OutputFormatter::Csv);
+    }
+
+    #[test]
+    fn dump_device_configuration() {
+        let mock = MockTransport::default();
+        mock.respond(b"42.000\n");
+        mock.respond(b"1.500\n");
+        mock.respond(b"1\n");
+        mock.respond(b"43.000\n");
+        mock.respond(b"2.000\n");
+        mock.respond(b"DHCP:0\nIP:192.168.1.198\nNETMASK:255.255.255.0\nGateWay:192.168.1.1\nMAC:88-06-00-00-ff-ff\nPORT:18190\nBAUDRATE:115200\n");
+        let mut kwr103 = Kwr103::from(mock);
+
+        assert_eq!(
+            dump(&mut kwr103),
+            json!({
+                "voltage": 42.0,
+                "current": 1.5,
+                "output": true,
+                "ovp": 43.0,
+                "ocp": 2.0,
+                "info": {
+                    "dhcp": false,
+                    "ip": "192.168.1.198",
+                    "netmask": "255.255.255.0",
+                    "gateway": "192.168.1.1",
+                    "mac": "88:06:00:00:ff:ff",
+                    "port": 18190,
+                    "baud": 115200,
+                },
+                "errors": [],
+            })
+        );
+    }
+
+    #[test]
+    fn dump_reports_failed_fields() {
+        let mock = MockTransport::default();
+        mock.respond(b"42.000\n");
+        mock.respond(b"garbage\n");
+        let mut kwr103 = Kwr103::from(mock);
+
+        let dump = dump(&mut kwr103);
+        assert_eq!(dump["voltage"], json!(42.0));
+        assert_eq!(dump["current"], Value::Null);
+        assert_eq!(dump["output"], Value::Null);
+        assert_eq!(dump["ovp"], Value::Null);
+        assert_eq!(dump["ocp"], Value::Null);
+        assert_eq!(dump["info"], Value::Null);
+
+        let failed: Vec<&str> = dump["errors"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|e| e["field"].as_str().unwrap())
+            .collect();
+        assert_eq!(failed, vec!["current", "output", "ovp", "ocp", "info"]);
+    }
 }
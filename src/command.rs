@@ -1,86 +1,175 @@
 //! Command and query types to interact with the power supply
+//!
+//! Some mnemonics are only supported by certain models or firmware revisions. Power supplies not
+//! supporting them respond with garbage or not at all, which is rejected as
+//! [`ResponseError::Invalid`] or [`ResponseError::Incomplete`]:
+//! - `VSLEW` for [`VoltageSlew`]
+//! - `ISLEW` for [`CurrentSlew`]
+//! - `PSET` for [`PowerLimit`]
+//! - `SENSEFAULT` for [`SenseFault`]
+//! - `OUTTIME` for [`OutputOnTime`]
+//! - `OFFTIME` for [`AutoOffTimer`]
+//! - `:SYST:NAME` for [`DeviceName`]
+//! - `:SYST:BRIGHT` for [`DisplayBrightness`] (unverified)
+//! - `TERM` for [`BusTermination`] (unverified)
+//! - `PANEL` for [`PanelActivity`] (unverified)
+//! - `VIN` for [`InputVoltage`] (unverified)
+//! - `TEMP` for [`Temperature`] (unverified)
 
+use std::ops::{Add, Sub};
 use std::str::FromStr;
+use std::time::{Duration, SystemTime};
 use std::{fmt, net};
 
-use crate::{Command, Query, ResponseError};
+use serde::{Serialize, Serializer};
+
+use crate::usb::BaudRate;
+use crate::{Command, DeviceId, Query, ResponseError, Setting, TransactionError, Verifiable};
+
+/// Tolerance in volts for a voltage readback to match its setting
+const VOLTAGE_TOLERANCE: f32 = 0.005;
+
+/// Tolerance in ampere for a current readback to match its setting
+const CURRENT_TOLERANCE: f32 = 0.0005;
 
 /// Representing the state of a switchable feature or output
-#[derive(Debug, PartialEq, Clone, Copy)]
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
 pub enum Switch {
     /// Disable feature or output
+    #[default]
     Off = 0,
     /// Enable feature or output
     On = 1,
 }
 
-/// Output voltage setting in units of volts
-#[derive(Debug, PartialEq)]
-pub struct Voltage(pub f32);
+impl Switch {
+    /// Whether the feature or output is enabled
+    pub fn is_on(self) -> bool {
+        self == Switch::On
+    }
+}
 
-impl Query for Voltage {
-    fn serialize(device_id: Option<u8>) -> Vec<u8> {
-        match device_id {
-            Some(id) => format!("VSET{:02}?\n", id),
-            None => String::from("VSET?\n"),
+impl From<bool> for Switch {
+    fn from(on: bool) -> Self {
+        if on {
+            Switch::On
+        } else {
+            Switch::Off
         }
-        .into_bytes()
     }
+}
 
-    fn parse(bytes: &[u8]) -> std::result::Result<Self, ResponseError> {
-        Ok(Self(parse_single_value(bytes)?))
+impl From<Switch> for bool {
+    fn from(switch: Switch) -> Self {
+        switch.is_on()
     }
 }
 
-impl Command for Voltage {
-    fn serialize(cmd: Self, device_id: Option<u8>) -> Vec<u8> {
-        match device_id {
-            Some(id) => format!("VSET{:02}:{:.3}\n", id, cmd.0),
-            None => format!("VSET:{:.3}\n", cmd.0),
+impl Serialize for Switch {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_bool(self.is_on())
+    }
+}
+
+/// Implement [`Setting`] with an `f32` value for a newtype `$name` using `$mnemonic`
+macro_rules! analog_setting {
+    ($name:ident, $mnemonic:literal) => {
+        impl Setting for $name {
+            type Value = f32;
+            const MNEMONIC: &'static str = $mnemonic;
+
+            fn from_value(value: f32) -> Self {
+                Self(value)
+            }
+
+            fn value(&self) -> &f32 {
+                &self.0
+            }
         }
-        .into_bytes()
+    };
+}
+
+/// Output voltage setting in units of volts
+#[derive(Debug, PartialEq, PartialOrd, Clone, Default)]
+pub struct Voltage(pub f32);
+
+analog_setting!(Voltage, "VSET");
+
+impl Verifiable for Voltage {
+    type Readback = Voltage;
+
+    fn matches(&self, readback: &Self::Readback) -> bool {
+        (self.0 - readback.0).abs() <= VOLTAGE_TOLERANCE
     }
 }
 
 /// Output current setting in units of ampere
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, PartialOrd, Clone, Default)]
 pub struct Current(pub f32);
 
-impl Query for Current {
-    fn serialize(device_id: Option<u8>) -> Vec<u8> {
-        match device_id {
-            Some(id) => format!("ISET{:02}?\n", id),
-            None => String::from("ISET?\n"),
-        }
-        .into_bytes()
+analog_setting!(Current, "ISET");
+
+impl Verifiable for Current {
+    type Readback = Current;
+
+    fn matches(&self, readback: &Self::Readback) -> bool {
+        (self.0 - readback.0).abs() <= CURRENT_TOLERANCE
     }
+}
 
-    fn parse(bytes: &[u8]) -> std::result::Result<Self, ResponseError> {
-        Ok(Self(parse_single_value(bytes)?))
+/// Output voltage slew-rate limit in units of volts per second
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct VoltageSlew(pub f32);
+
+analog_setting!(VoltageSlew, "VSLEW");
+
+/// Output current slew-rate limit in units of ampere per second
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct CurrentSlew(pub f32);
+
+analog_setting!(CurrentSlew, "ISLEW");
+
+/// Output power limit in units of watts
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct PowerLimit(pub f32);
+
+analog_setting!(PowerLimit, "PSET");
+
+impl<S: Setting> Command for S {
+    fn serialize(cmd: Self, device_id: DeviceId) -> Vec<u8> {
+        format!("{}{}:{:.3}\n", S::MNEMONIC, device_id, cmd.value()).into_bytes()
     }
 }
 
-impl Command for Current {
-    fn serialize(cmd: Self, device_id: Option<u8>) -> Vec<u8> {
-        match device_id {
-            Some(id) => format!("ISET{:02}:{:.3}\n", id, cmd.0),
-            None => format!("ISET:{:.3}\n", cmd.0),
-        }
-        .into_bytes()
+impl<S: Setting> Query for S {
+    fn serialize(device_id: DeviceId) -> Vec<u8> {
+        format!("{}{}?\n", S::MNEMONIC, device_id).into_bytes()
+    }
+
+    fn parse(bytes: &[u8]) -> std::result::Result<Self, ResponseError> {
+        Ok(S::from_value(parse_single_value(bytes)?))
     }
 }
 
+/// Over-voltage protection threshold in units of volts
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct OverVoltageProtection(pub f32);
+
+analog_setting!(OverVoltageProtection, "OVP");
+
+/// Over-current protection threshold in units of ampere
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct OverCurrentProtection(pub f32);
+
+analog_setting!(OverCurrentProtection, "OCP");
+
 /// Output power switch On/Off
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone, Default)]
 pub struct Output(pub Switch);
 
 impl Query for Output {
-    fn serialize(device_id: Option<u8>) -> Vec<u8> {
-        match device_id {
-            Some(id) => format!("OUT{:02}?\n", id),
-            None => String::from("OUT?\n"),
-        }
-        .into_bytes()
+    fn serialize(device_id: DeviceId) -> Vec<u8> {
+        format!("OUT{}?\n", device_id).into_bytes()
     }
 
     fn parse(bytes: &[u8]) -> std::result::Result<Self, ResponseError> {
@@ -89,17 +178,24 @@ impl Query for Output {
 }
 
 impl Command for Output {
-    fn serialize(cmd: Self, device_id: Option<u8>) -> Vec<u8> {
-        match device_id {
-            Some(id) => format!("OUT{:02}:{}\n", id, cmd.0 as u8),
-            None => format!("OUT:{}\n", cmd.0 as u8),
-        }
-        .into_bytes()
+    fn serialize(cmd: Self, device_id: DeviceId) -> Vec<u8> {
+        format!("OUT{}:{}\n", device_id, cmd.0 as u8).into_bytes()
+    }
+}
+
+impl Verifiable for Output {
+    type Readback = Output;
+
+    fn matches(&self, readback: &Self::Readback) -> bool {
+        self.0 == readback.0
     }
 }
 
 /// Actual output voltage and current state
-#[derive(Debug, PartialEq)]
+///
+/// Measurements are displayed with 3 decimal places, use the formatter precision to show more,
+/// e.g. `format!("{:.4}", status)` for sub-milliampere currents.
+#[derive(Debug, PartialEq, Default, Serialize)]
 pub struct Status {
     /// Output power state On/Off
     pub power: Switch,
@@ -109,29 +205,277 @@ pub struct Status {
     pub current: f32,
 }
 
+impl Status {
+    /// Whether the measured voltage and current are finite numbers
+    pub fn is_valid(&self) -> bool {
+        self.voltage.is_finite() && self.current.is_finite()
+    }
+
+    /// Whether `other` has the same output state and voltage and current differing by at most
+    /// `tol` each
+    pub fn approx_eq(&self, other: &Status, tol: f32) -> bool {
+        self.power == other.power
+            && (self.voltage - other.voltage).abs() <= tol
+            && (self.current - other.current).abs() <= tol
+    }
+}
+
+/// A measured output voltage and current, independent of the query it was obtained by
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Measurement {
+    /// Output voltage in volts
+    pub voltage: f32,
+    /// Output current in ampere
+    pub current: f32,
+    /// Output power in watts, derived from voltage and current
+    pub power: f32,
+    /// Time the measurement was constructed
+    pub timestamp: SystemTime,
+}
+
+impl Measurement {
+    /// Measurement of `voltage` and `current`, timestamped now
+    pub fn new(voltage: f32, current: f32) -> Self {
+        Self {
+            voltage,
+            current,
+            power: voltage * current,
+            timestamp: SystemTime::now(),
+        }
+    }
+}
+
+impl From<&Status> for Measurement {
+    fn from(status: &Status) -> Self {
+        Measurement::new(status.voltage, status.current)
+    }
+}
+
 impl Query for Status {
-    fn serialize(device_id: Option<u8>) -> Vec<u8> {
-        match device_id {
-            Some(id) => format!("OUT{:02}?\nVOUT{:02}?\nIOUT{:02}?\n", id, id, id),
-            None => String::from("OUT?\nVOUT?\nIOUT?\n"),
+    const RESPONSE_LINES: usize = 3;
+
+    fn serialize(device_id: DeviceId) -> Vec<u8> {
+        format!("OUT{0}?\nVOUT{0}?\nIOUT{0}?\n", device_id).into_bytes()
+    }
+
+    fn parse(bytes: &[u8]) -> std::result::Result<Self, ResponseError> {
+        let mut framer = ResponseFramer::new(bytes);
+
+        Ok(Self {
+            power: framer.next_value()?,
+            voltage: framer.next_value()?,
+            current: framer.next_value()?,
+        })
+    }
+}
+
+/// Regulation mode of the power supply output
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum RegulationMode {
+    /// Constant voltage, the output voltage is regulated to the voltage setting
+    ConstantVoltage,
+    /// Constant current, the output current is limited to the current setting
+    ConstantCurrent,
+}
+
+impl Serialize for RegulationMode {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl RegulationMode {
+    /// Decode the regulation mode from bit 0 of the status byte (1 = CV, 0 = CC)
+    pub fn from_status_byte(status: u8) -> Self {
+        if status & 0x01 != 0 {
+            RegulationMode::ConstantVoltage
+        } else {
+            RegulationMode::ConstantCurrent
         }
+    }
+}
+
+/// Regulation mode as reported by the status byte (`STATUS?`)
+#[derive(Debug, PartialEq)]
+pub struct Mode(pub RegulationMode);
+
+impl Query for Mode {
+    fn serialize(device_id: DeviceId) -> Vec<u8> {
+        format!("STATUS{}?\n", device_id).into_bytes()
+    }
+
+    fn parse(bytes: &[u8]) -> std::result::Result<Self, ResponseError> {
+        Ok(Self(RegulationMode::from_status_byte(parse_single_value(
+            bytes,
+        )?)))
+    }
+}
+
+/// Output state, regulation mode and measurements obtained in a single transaction
+///
+/// The response lines are expected in the order output state, status byte, measured voltage and
+/// measured current.
+#[derive(Debug, PartialEq, Clone, Copy, Serialize)]
+pub struct OutputState {
+    /// Output power state On/Off
+    pub power: Switch,
+    /// Regulation mode
+    pub mode: RegulationMode,
+    /// Measured output voltage in volts
+    pub voltage: f32,
+    /// Measured output current in ampere
+    pub current: f32,
+}
+
+impl Query for OutputState {
+    const RESPONSE_LINES: usize = 4;
+
+    fn serialize(device_id: DeviceId) -> Vec<u8> {
+        format!("OUT{0}?\nSTATUS{0}?\nVOUT{0}?\nIOUT{0}?\n", device_id).into_bytes()
+    }
+
+    fn parse(bytes: &[u8]) -> std::result::Result<Self, ResponseError> {
+        let mut framer = ResponseFramer::new(bytes);
+
+        Ok(Self {
+            power: framer.next_value()?,
+            mode: RegulationMode::from_status_byte(framer.next_value()?),
+            voltage: framer.next_value()?,
+            current: framer.next_value()?,
+        })
+    }
+}
+
+/// Output state, measurements, settings and regulation mode obtained in a single transaction
+///
+/// The response lines are expected in the order output state, measured voltage, measured
+/// current, voltage setting, current setting and status byte. Like for [`Status`], the formatter
+/// precision overrides the 3 decimal places displayed.
+#[derive(Debug, PartialEq)]
+pub struct FullStatus {
+    /// Output power state On/Off
+    pub power: Switch,
+    /// Measured output voltage in volts
+    pub voltage: f32,
+    /// Measured output current in ampere
+    pub current: f32,
+    /// Output voltage setting in volts
+    pub voltage_setpoint: f32,
+    /// Output current setting in ampere
+    pub current_setpoint: f32,
+    /// Regulation mode
+    pub mode: RegulationMode,
+}
+
+impl Query for FullStatus {
+    const RESPONSE_LINES: usize = 6;
+
+    fn serialize(device_id: DeviceId) -> Vec<u8> {
+        format!(
+            "OUT{0}?\nVOUT{0}?\nIOUT{0}?\nVSET{0}?\nISET{0}?\nSTATUS{0}?\n",
+            device_id
+        )
         .into_bytes()
     }
 
     fn parse(bytes: &[u8]) -> std::result::Result<Self, ResponseError> {
-        let response = String::from_utf8_lossy(bytes);
-        let mut tokens = response.split_whitespace();
+        let mut framer = ResponseFramer::new(bytes);
 
         Ok(Self {
-            power: parse_next_token(&mut tokens)?,
-            voltage: parse_next_token(&mut tokens)?,
-            current: parse_next_token(&mut tokens)?,
+            power: framer.next_value()?,
+            voltage: framer.next_value()?,
+            current: framer.next_value()?,
+            voltage_setpoint: framer.next_value()?,
+            current_setpoint: framer.next_value()?,
+            mode: RegulationMode::from_status_byte(framer.next_value()?),
         })
     }
 }
 
+/// Deviation of the measured output from the settings, see [`crate::Kwr103::regulation_report`]
+///
+/// Errors are measured minus set value, so in constant voltage mode the current error is
+/// typically negative and the voltage error close to zero, and vice versa in constant current
+/// mode.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct RegulationReport {
+    /// Measured minus set output voltage in volts
+    pub voltage_error: f32,
+    /// Measured minus set output current in ampere
+    pub current_error: f32,
+    /// Regulation mode
+    pub mode: RegulationMode,
+}
+
+/// Output voltage and current settings, as confirmed by the power supply
+#[derive(Debug, PartialEq, Clone, Copy, Default, Serialize)]
+pub struct Setpoints {
+    /// Output voltage setting in volts
+    pub voltage: f32,
+    /// Output current setting in ampere
+    pub current: f32,
+}
+
+impl From<&FullStatus> for RegulationReport {
+    fn from(status: &FullStatus) -> Self {
+        Self {
+            voltage_error: status.voltage - status.voltage_setpoint,
+            current_error: status.current - status.current_setpoint,
+            mode: status.mode,
+        }
+    }
+}
+
+/// Hardware MAC address
+///
+/// Parses from both dash and colon separated notation and displays in canonical lowercase colon
+/// separated form, e.g. `88:06:00:00:ff:ff`. Parsing is strict: exactly six octets of two hex
+/// digits each, separated consistently by either `-` or `:`.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub struct MacAddress(pub [u8; 6]);
+
+impl FromStr for MacAddress {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        const ERR: &str = "Invalid MAC address (must be six hex octets separated by '-' or ':')";
+
+        let separator = if s.contains('-') { '-' } else { ':' };
+        let mut octets = [0u8; 6];
+        let mut parts = s.split(separator);
+        for octet in octets.iter_mut() {
+            let part = parts.next().ok_or(ERR)?;
+            if part.len() != 2 || !part.bytes().all(|b| b.is_ascii_hexdigit()) {
+                return Err(ERR);
+            }
+            *octet = u8::from_str_radix(part, 16).map_err(|_| ERR)?;
+        }
+        if parts.next().is_some() {
+            return Err(ERR);
+        }
+        Ok(MacAddress(octets))
+    }
+}
+
+impl Serialize for MacAddress {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl fmt::Display for MacAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let [a, b, c, d, e, g] = self.0;
+        write!(
+            f,
+            "{:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}",
+            a, b, c, d, e, g
+        )
+    }
+}
+
 /// System settings information
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Serialize)]
 pub struct DeviceInfo {
     /// Obtain IP address by DHCP
     pub dhcp: Switch,
@@ -142,46 +486,48 @@ pub struct DeviceInfo {
     /// Gateway
     pub gateway: net::Ipv4Addr,
     /// MAC address
-    pub mac: String,
+    pub mac: MacAddress,
     /// UDP port,
     pub port: u16,
     /// Serial baud rate
-    pub baud: u32,
+    pub baud: BaudRate,
 }
 
 impl Query for DeviceInfo {
-    fn serialize(_device_id: Option<u8>) -> Vec<u8> {
+    const RESPONSE_LINES: usize = 7;
+
+    fn serialize(_device_id: DeviceId) -> Vec<u8> {
         String::from(":SYST:DEVINFO?\n").into_bytes()
     }
 
     fn parse(bytes: &[u8]) -> std::result::Result<Self, ResponseError> {
-        let response = String::from_utf8_lossy(bytes);
-        let mut tokens = response.split_whitespace();
+        let mut framer = ResponseFramer::new(bytes);
 
         Ok(Self {
-            dhcp: strip_and_parse_next_token("DHCP:", &mut tokens)?,
-            ip: strip_and_parse_next_token("IP:", &mut tokens)?,
-            netmask: strip_and_parse_next_token("NETMASK:", &mut tokens)?,
-            gateway: strip_and_parse_next_token("GateWay:", &mut tokens)?,
-            mac: strip_and_parse_next_token("MAC:", &mut tokens)?,
-            port: strip_and_parse_next_token("PORT:", &mut tokens)?,
-            baud: strip_and_parse_next_token("BAUDRATE:", &mut tokens)?,
+            dhcp: framer.next_prefixed("DHCP:")?,
+            ip: framer.next_prefixed("IP:")?,
+            netmask: framer.next_prefixed("NETMASK:")?,
+            gateway: framer.next_prefixed("GateWay:")?,
+            mac: framer.next_prefixed("MAC:")?,
+            port: framer.next_prefixed("PORT:")?,
+            baud: BaudRate::try_from(framer.next_prefixed::<u32>("BAUDRATE:")?)
+                .map_err(|_| ResponseError::Invalid)?,
         })
     }
 }
 
 /// Use DHCP to obtain an IP address
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone, Default)]
 pub struct Dhcp(pub Switch);
 
 impl Command for Dhcp {
-    fn serialize(cmd: Self, _device_id: Option<u8>) -> Vec<u8> {
+    fn serialize(cmd: Self, _device_id: DeviceId) -> Vec<u8> {
         format!(":SYST:DHCP {}\n", cmd.0 as u8).into_bytes()
     }
 }
 
 impl Query for Dhcp {
-    fn serialize(_device_id: Option<u8>) -> Vec<u8> {
+    fn serialize(_device_id: DeviceId) -> Vec<u8> {
         String::from(":SYST:DHCP?\n").into_bytes()
     }
 
@@ -190,258 +536,2151 @@ impl Query for Dhcp {
     }
 }
 
-impl std::str::FromStr for Switch {
-    type Err = &'static str;
+/// UDP port the power supply listens on for ethernet connections
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct UdpPort(pub u16);
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s {
-            "0" => Ok(Switch::Off),
-            "1" => Ok(Switch::On),
-            "off" => Ok(Switch::Off),
-            "on" => Ok(Switch::On),
-            _ => Err("Invalid value for Switch (must be either 0/1 or on/off)"),
+impl Command for UdpPort {
+    fn serialize(cmd: Self, _device_id: DeviceId) -> Vec<u8> {
+        format!(":SYST:PORT {}\n", cmd.0).into_bytes()
+    }
+
+    fn validate(&self) -> Result<(), TransactionError> {
+        if self.0 == 0 {
+            return Err(TransactionError::InvalidConfiguration(
+                "UDP port must not be 0".to_string(),
+            ));
         }
+        Ok(())
     }
 }
 
-impl fmt::Display for Status {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(
-            f,
-            "Output: {:?}, Voltage[V]: {:5.3}, Current[A]: {:5.3}",
-            self.power, self.voltage, self.current,
-        )
+impl Query for UdpPort {
+    fn serialize(_device_id: DeviceId) -> Vec<u8> {
+        String::from(":SYST:PORT?\n").into_bytes()
     }
-}
 
-impl fmt::Display for DeviceInfo {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(
-            f,
-            "DHCP:       {:?}\n\
-             IP Address: {}\n\
-             Netmask:    {}\n\
-             Gateway:    {}\n\
-             MAC:        {}\n\
-             UDP Port:   {}\n\
-             Baudrate:   {}",
-            self.dhcp, self.ip, self.netmask, self.gateway, self.mac, self.port, self.baud
-        )
+    fn parse(bytes: &[u8]) -> std::result::Result<Self, ResponseError> {
+        Ok(Self(parse_single_value::<u16>(bytes)?))
     }
 }
 
-fn parse_single_value<T: FromStr>(bytes: &[u8]) -> Result<T, ResponseError> {
-    String::from_utf8_lossy(bytes)
-        .strip_suffix('\n')
-        .ok_or(ResponseError::Incomplete)?
-        .parse()
-        .map_err(|_| ResponseError::Invalid)
+/// Front panel display brightness in percent, from 0 to [`DisplayBrightness::MAX`]
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct DisplayBrightness(pub u8);
+
+impl DisplayBrightness {
+    /// Maximum brightness
+    pub const MAX: u8 = 100;
 }
 
-fn parse_next_token<'a, I, T>(iter: &mut I) -> Result<T, ResponseError>
-where
-    I: Iterator<Item = &'a str>,
-    T: FromStr,
-{
-    iter.next()
-        .ok_or(ResponseError::Incomplete)?
-        .parse()
-        .map_err(|_| ResponseError::Invalid)
+impl Command for DisplayBrightness {
+    fn serialize(cmd: Self, _device_id: DeviceId) -> Vec<u8> {
+        format!(":SYST:BRIGHT {}\n", cmd.0).into_bytes()
+    }
+
+    fn validate(&self) -> Result<(), TransactionError> {
+        if self.0 > Self::MAX {
+            return Err(TransactionError::InvalidConfiguration(format!(
+                "Display brightness must be in [0; {}]",
+                Self::MAX
+            )));
+        }
+        Ok(())
+    }
 }
 
-fn strip_and_parse_next_token<'a, I, T>(prefix: &str, iter: &mut I) -> Result<T, ResponseError>
-where
-    I: Iterator<Item = &'a str>,
-    T: FromStr,
-{
-    iter.next()
-        .ok_or(ResponseError::Incomplete)?
-        .strip_prefix(prefix)
-        .ok_or(ResponseError::Invalid)?
-        .parse()
-        .map_err(|_| ResponseError::Invalid)
+impl Query for DisplayBrightness {
+    fn serialize(_device_id: DeviceId) -> Vec<u8> {
+        String::from(":SYST:BRIGHT?\n").into_bytes()
+    }
+
+    fn parse(bytes: &[u8]) -> std::result::Result<Self, ResponseError> {
+        match parse_single_value::<u8>(bytes)? {
+            brightness if brightness <= Self::MAX => Ok(Self(brightness)),
+            _ => Err(ResponseError::Invalid),
+        }
+    }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+/// Remote control mode, locking the front panel while [`Switch::On`]
+///
+/// Queried answers are parsed like for [`PanelState`].
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct RemoteControl(pub Switch);
 
-    #[test]
-    fn switch_from_string() {
-        assert_eq!(Ok(Switch::Off), "0".parse());
-        assert_eq!(Ok(Switch::On), "1".parse());
-        assert_eq!(Ok(Switch::Off), "off".parse());
-        assert_eq!(Ok(Switch::On), "on".parse());
-        assert!("2".parse::<Switch>().is_err());
-        assert!("_".parse::<Switch>().is_err());
+impl Command for RemoteControl {
+    fn serialize(cmd: Self, _device_id: DeviceId) -> Vec<u8> {
+        format!(":SYST:LOCK {}\n", cmd.0 as u8).into_bytes()
     }
+}
 
-    #[test]
-    fn switch_into_u8() {
-        assert_eq!(0u8, Switch::Off as u8);
-        assert_eq!(1u8, Switch::On as u8);
+impl Query for RemoteControl {
+    fn serialize(_device_id: DeviceId) -> Vec<u8> {
+        String::from(":SYST:LOCK?\n").into_bytes()
     }
 
-    #[test]
-    fn query_voltage() {
-        assert_eq!(
-            <Voltage as Query>::serialize(Some(2)),
-            "VSET02?\n".as_bytes()
-        );
-        assert_eq!(<Voltage as Query>::serialize(None), "VSET?\n".as_bytes());
-        assert_eq!(
-            <Voltage as Query>::parse("42.123\n".as_bytes()).unwrap(),
-            Voltage(42.123)
-        );
+    fn parse(bytes: &[u8]) -> std::result::Result<Self, ResponseError> {
+        match <PanelState as Query>::parse(bytes)? {
+            PanelState::Local => Ok(Self(Switch::Off)),
+            PanelState::RemoteLocked => Ok(Self(Switch::On)),
+        }
     }
+}
 
-    #[test]
+/// RS485 bus termination resistor On/Off, to be enabled on the units at both ends of the bus
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct BusTermination(pub Switch);
+
+impl Command for BusTermination {
+    fn serialize(cmd: Self, device_id: DeviceId) -> Vec<u8> {
+        format!("TERM{}:{}\n", device_id, cmd.0 as u8).into_bytes()
+    }
+}
+
+impl Query for BusTermination {
+    fn serialize(device_id: DeviceId) -> Vec<u8> {
+        format!("TERM{}?\n", device_id).into_bytes()
+    }
+
+    fn parse(bytes: &[u8]) -> std::result::Result<Self, ResponseError> {
+        Ok(Self(parse_single_value::<Switch>(bytes)?))
+    }
+}
+
+/// Whether the remote sense leads are disconnected
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct SenseFault(pub bool);
+
+impl Query for SenseFault {
+    fn serialize(device_id: DeviceId) -> Vec<u8> {
+        format!("SENSEFAULT{}?\n", device_id).into_bytes()
+    }
+
+    fn parse(bytes: &[u8]) -> std::result::Result<Self, ResponseError> {
+        Ok(Self(parse_single_value::<Switch>(bytes)?.is_on()))
+    }
+}
+
+/// Whether an operator is currently adjusting the front panel, e.g. turning a knob
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct PanelActivity(pub bool);
+
+impl Query for PanelActivity {
+    fn serialize(device_id: DeviceId) -> Vec<u8> {
+        format!("PANEL{}?\n", device_id).into_bytes()
+    }
+
+    fn parse(bytes: &[u8]) -> std::result::Result<Self, ResponseError> {
+        Ok(Self(parse_single_value::<Switch>(bytes)?.is_on()))
+    }
+}
+
+/// Accumulated time the output has been switched on, reported in whole seconds
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct OutputOnTime(pub Duration);
+
+impl Query for OutputOnTime {
+    fn serialize(device_id: DeviceId) -> Vec<u8> {
+        format!("OUTTIME{}?\n", device_id).into_bytes()
+    }
+
+    fn parse(bytes: &[u8]) -> std::result::Result<Self, ResponseError> {
+        Ok(Self(Duration::from_secs(parse_single_value::<u64>(bytes)?)))
+    }
+}
+
+/// Timer switching the output off once it has been on for the given time, `None` if disabled
+///
+/// The power supply stores the timer in whole seconds, a value of 0 disables it. Fractional
+/// seconds are truncated.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct AutoOffTimer(pub Option<Duration>);
+
+impl Command for AutoOffTimer {
+    fn serialize(cmd: Self, device_id: DeviceId) -> Vec<u8> {
+        let seconds = cmd.0.map_or(0, |timer| timer.as_secs());
+        format!("OFFTIME{}:{}\n", device_id, seconds).into_bytes()
+    }
+
+    fn validate(&self) -> Result<(), TransactionError> {
+        if self.0.is_some_and(|timer| timer.as_secs() == 0) {
+            return Err(TransactionError::InvalidConfiguration(
+                "Auto-off timer must be at least 1 s, use None to disable".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl Query for AutoOffTimer {
+    fn serialize(device_id: DeviceId) -> Vec<u8> {
+        format!("OFFTIME{}?\n", device_id).into_bytes()
+    }
+
+    fn parse(bytes: &[u8]) -> std::result::Result<Self, ResponseError> {
+        let seconds = parse_single_value::<u64>(bytes)?;
+        Ok(Self((seconds > 0).then(|| Duration::from_secs(seconds))))
+    }
+}
+
+/// AC input (mains) voltage in volts, as measured by the power supply
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct InputVoltage(pub f32);
+
+impl Query for InputVoltage {
+    fn serialize(device_id: DeviceId) -> Vec<u8> {
+        format!("VIN{}?\n", device_id).into_bytes()
+    }
+
+    fn parse(bytes: &[u8]) -> std::result::Result<Self, ResponseError> {
+        let volts = parse_single_value::<f32>(bytes)?;
+        if !volts.is_finite() || volts < 0.0 {
+            return Err(ResponseError::Invalid);
+        }
+        Ok(Self(volts))
+    }
+}
+
+/// Internal temperature in degrees Celsius
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Temperature(pub f32);
+
+impl Query for Temperature {
+    fn serialize(device_id: DeviceId) -> Vec<u8> {
+        format!("TEMP{}?\n", device_id).into_bytes()
+    }
+
+    fn parse(bytes: &[u8]) -> std::result::Result<Self, ResponseError> {
+        let celsius = parse_single_value::<f32>(bytes)?;
+        if !celsius.is_finite() {
+            return Err(ResponseError::Invalid);
+        }
+        Ok(Self(celsius))
+    }
+}
+
+/// Optional queries supported by a particular power supply, see `Kwr103::detect_capabilities`
+#[derive(Debug, PartialEq, Clone, Copy, Default, Serialize)]
+pub struct Capabilities {
+    /// Over-voltage protection level can be queried, see [`OverVoltageProtection`]
+    pub over_voltage_protection: bool,
+    /// Over-current protection level can be queried, see [`OverCurrentProtection`]
+    pub over_current_protection: bool,
+    /// Internal temperature is reported, see [`Temperature`]
+    pub temperature: bool,
+    /// Status byte is reported, see [`Mode`]
+    pub status_byte: bool,
+}
+
+/// User defined label of the power supply, stored in non-volatile memory
+///
+/// Labels consist of 1 to [`DeviceName::MAX_LEN`] ASCII alphanumeric characters, spaces, `-`,
+/// `_` or `.`.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct DeviceName(pub String);
+
+impl DeviceName {
+    /// Maximum number of characters of a label
+    pub const MAX_LEN: usize = 16;
+}
+
+impl Command for DeviceName {
+    fn serialize(cmd: Self, _device_id: DeviceId) -> Vec<u8> {
+        format!(":SYST:NAME {}\n", cmd.0).into_bytes()
+    }
+
+    fn validate(&self) -> Result<(), TransactionError> {
+        let valid_char = |c: char| c.is_ascii_alphanumeric() || " -_.".contains(c);
+        if self.0.is_empty() || self.0.len() > Self::MAX_LEN || !self.0.chars().all(valid_char) {
+            return Err(TransactionError::InvalidConfiguration(format!(
+                "Invalid device name '{}' (must be 1 to {} ASCII alphanumeric characters, \
+                 spaces, '-', '_' or '.')",
+                self.0,
+                Self::MAX_LEN
+            )));
+        }
+        Ok(())
+    }
+}
+
+impl Query for DeviceName {
+    fn serialize(_device_id: DeviceId) -> Vec<u8> {
+        String::from(":SYST:NAME?\n").into_bytes()
+    }
+
+    fn parse(bytes: &[u8]) -> std::result::Result<Self, ResponseError> {
+        let response = String::from_utf8_lossy(bytes);
+        let name = strip_echo(&response)
+            .strip_suffix('\n')
+            .ok_or(ResponseError::Incomplete)?
+            .trim_end_matches('\r');
+        if name.trim().is_empty() {
+            return Err(ResponseError::Incomplete);
+        }
+        Ok(Self(name.to_string()))
+    }
+}
+
+/// Memory slot of a stored preset, numbered 1 to [`PresetSlot::MAX`]
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct PresetSlot(u8);
+
+impl PresetSlot {
+    /// Highest preset slot number
+    pub const MAX: u8 = 5;
+
+    /// Create a preset slot, if `slot` is in [1; [`PresetSlot::MAX`]]
+    pub fn new(slot: u8) -> Option<Self> {
+        (1..=Self::MAX).contains(&slot).then_some(Self(slot))
+    }
+
+    /// The slot number
+    pub fn number(self) -> u8 {
+        self.0
+    }
+}
+
+impl fmt::Display for PresetSlot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "M{}", self.0)
+    }
+}
+
+/// Preset the output is currently tracking, `None` if the settings were not recalled from a preset
+///
+/// The power supply reports the slot number, or `0` resp. `NONE` if no preset is active.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct ActivePreset(pub Option<PresetSlot>);
+
+impl Query for ActivePreset {
+    fn serialize(device_id: DeviceId) -> Vec<u8> {
+        format!("PRESET{}?\n", device_id).into_bytes()
+    }
+
+    fn parse(bytes: &[u8]) -> std::result::Result<Self, ResponseError> {
+        let value = parse_single_value::<String>(bytes)?;
+        if value.trim().eq_ignore_ascii_case("none") {
+            return Ok(Self(None));
+        }
+        match value.trim().parse::<u8>() {
+            Ok(0) => Ok(Self(None)),
+            Ok(slot) => Ok(Self(Some(
+                PresetSlot::new(slot).ok_or(ResponseError::Invalid)?,
+            ))),
+            Err(_) => Err(ResponseError::Invalid),
+        }
+    }
+}
+
+/// Whether the front panel is operable or locked by [`RemoteControl`]
+///
+/// Reads the same lock state as the [`RemoteControl`] query. Depending on the firmware, the
+/// power supply answers with `0`/`1` or `LOCAL`/`REMOTE`.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum PanelState {
+    /// Front panel keys and knob are operable
+    Local,
+    /// Front panel is locked for remote control
+    RemoteLocked,
+}
+
+impl Query for PanelState {
+    fn serialize(_device_id: DeviceId) -> Vec<u8> {
+        String::from(":SYST:LOCK?\n").into_bytes()
+    }
+
+    fn parse(bytes: &[u8]) -> std::result::Result<Self, ResponseError> {
+        match parse_single_value::<String>(bytes)?
+            .trim()
+            .to_ascii_uppercase()
+            .as_str()
+        {
+            "0" | "LOCAL" => Ok(PanelState::Local),
+            "1" | "REMOTE" => Ok(PanelState::RemoteLocked),
+            _ => Err(ResponseError::Invalid),
+        }
+    }
+}
+
+/// State of the cooling fan
+///
+/// The power supply answers with the fan state, optionally followed by the fan speed, e.g.
+/// `1,2400`. Models not monitoring the fan speed only report the state, e.g. `1`.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct FanStatus {
+    /// Fan speed in revolutions per minute, if reported
+    pub rpm: Option<u32>,
+    /// Whether the fan is running
+    pub on: Switch,
+}
+
+impl Query for FanStatus {
+    fn serialize(device_id: DeviceId) -> Vec<u8> {
+        format!("FAN{}?\n", device_id).into_bytes()
+    }
+
+    fn parse(bytes: &[u8]) -> std::result::Result<Self, ResponseError> {
+        let value = parse_single_value::<String>(bytes)?;
+        let mut tokens = value
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|token| !token.is_empty());
+
+        let status = Self {
+            on: parse_next_token(&mut tokens)?,
+            rpm: tokens
+                .next()
+                .map(|rpm| rpm.parse().map_err(|_| ResponseError::Invalid))
+                .transpose()?,
+        };
+        if tokens.next().is_some() {
+            return Err(ResponseError::Invalid);
+        }
+        Ok(status)
+    }
+}
+
+/// Clear a latched over-voltage/over-current protection trip
+///
+/// This is a fire-and-forget command, the power supply does not acknowledge it. Query [`Output`]
+/// or [`Status`] afterwards to check whether the output can be enabled again.
+#[derive(Debug, PartialEq, Clone)]
+pub struct ClearProtection;
+
+impl Command for ClearProtection {
+    fn serialize(_cmd: Self, _device_id: DeviceId) -> Vec<u8> {
+        String::from(":PROT:CLE\n").into_bytes()
+    }
+}
+
+/// Trigger a single audible beep, e.g. to locate a power supply in a rack
+///
+/// This is independent of whether key press beeps are enabled.
+#[derive(Debug, PartialEq, Clone)]
+pub struct TriggerBeep;
+
+impl Command for TriggerBeep {
+    fn serialize(_cmd: Self, _device_id: DeviceId) -> Vec<u8> {
+        String::from(":SYST:BEEP\n").into_bytes()
+    }
+}
+
+/// Reset the power supply to its default settings (`*RST`)
+///
+/// This is a fire-and-forget command, the power supply does not acknowledge it. Allow the power
+/// supply a short delay to settle and query the relevant settings afterwards to confirm.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Reset;
+
+impl Command for Reset {
+    fn serialize(_cmd: Self, _device_id: DeviceId) -> Vec<u8> {
+        String::from("*RST\n").into_bytes()
+    }
+}
+
+/// Outcome of the power supply's built-in self-test
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct SelfTestResult {
+    /// Whether the self-test passed, i.e. reported code 0
+    pub passed: bool,
+    /// Result code as reported by the power supply
+    pub code: i32,
+}
+
+/// Run the built-in self-test (`*TST?`)
+#[derive(Debug, PartialEq)]
+pub struct SelfTest(pub SelfTestResult);
+
+impl Query for SelfTest {
+    fn serialize(_device_id: DeviceId) -> Vec<u8> {
+        String::from("*TST?\n").into_bytes()
+    }
+
+    fn parse(bytes: &[u8]) -> std::result::Result<Self, ResponseError> {
+        let code: i32 = parse_single_value(bytes)?;
+        Ok(Self(SelfTestResult {
+            passed: code == 0,
+            code,
+        }))
+    }
+}
+
+/// Rated maximum output voltage and current of a power supply model, and the smallest settable
+/// increments thereof
+///
+/// Querying `Ratings` reads the model identification (`*IDN?`) and resolves the ratings through
+/// [`Ratings::for_model`], as the device does not report its limits directly.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Ratings {
+    /// Maximum output voltage in volts
+    pub max_voltage: f32,
+    /// Maximum output current in ampere
+    pub max_current: f32,
+    /// Smallest settable voltage increment in volts
+    pub voltage_resolution: f32,
+    /// Smallest settable current increment in ampere
+    pub current_resolution: f32,
+}
+
+/// Known power supply models and their rated maximums
+const MODEL_RATINGS: &[(&str, Ratings)] = &[("KWR103", Ratings::KWR103)];
+
+impl Ratings {
+    /// Ratings of the KWR103
+    pub const KWR103: Ratings = Ratings {
+        max_voltage: 60.0,
+        max_current: 15.0,
+        voltage_resolution: 0.01,
+        current_resolution: 0.001,
+    };
+
+    /// Look up the ratings for a `model` string, e.g. `KWR103`
+    pub fn for_model(model: &str) -> Option<Ratings> {
+        MODEL_RATINGS
+            .iter()
+            .find(|(name, _)| model.contains(name))
+            .map(|(_, ratings)| *ratings)
+    }
+
+    /// Round voltage `v` to the nearest settable step
+    pub fn round_voltage(&self, v: f32) -> f32 {
+        round_to_step(v, self.voltage_resolution)
+    }
+
+    /// Round current `i` to the nearest settable step
+    pub fn round_current(&self, i: f32) -> f32 {
+        round_to_step(i, self.current_resolution)
+    }
+}
+
+fn round_to_step(value: f32, step: f32) -> f32 {
+    (value / step).round() * step
+}
+
+impl Query for Ratings {
+    fn serialize(_device_id: DeviceId) -> Vec<u8> {
+        String::from("*IDN?\n").into_bytes()
+    }
+
+    fn parse(bytes: &[u8]) -> std::result::Result<Self, ResponseError> {
+        let response = String::from_utf8_lossy(bytes);
+        if response.trim().is_empty() {
+            return Err(ResponseError::Incomplete);
+        }
+        Ratings::for_model(&response).ok_or(ResponseError::Invalid)
+    }
+}
+
+impl std::str::FromStr for Switch {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "0" => Ok(Switch::Off),
+            "1" => Ok(Switch::On),
+            "off" => Ok(Switch::Off),
+            "on" => Ok(Switch::On),
+            _ => Err("Invalid value for Switch (must be either 0/1 or on/off)"),
+        }
+    }
+}
+
+/// Implement `TryFrom<&[u8]>` for query responses `$name`, delegating to [`Query::parse`]
+macro_rules! try_from_response {
+    ($($name:ident),+) => {
+        $(
+            impl TryFrom<&[u8]> for $name {
+                type Error = ResponseError;
+
+                fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
+                    <$name as Query>::parse(bytes)
+                }
+            }
+        )+
+    };
+}
+
+try_from_response!(Status, DeviceInfo, Voltage, Current);
+
+/// Displays a numeric reading as is, or the sentinel `n/a` if it is not finite
+struct Reading(f32);
+
+impl fmt::Display for Reading {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match f.width() {
+            _ if self.0.is_finite() => fmt::Display::fmt(&self.0, f),
+            // right-align the sentinel like numbers unless requested otherwise
+            Some(width) if f.align().is_none() => write!(f, "{:>width$}", "n/a"),
+            _ => f.pad("n/a"),
+        }
+    }
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let precision = f.precision().unwrap_or(3);
+        write!(
+            f,
+            "Output: {:?}, Voltage[V]: {:5.precision$}, Current[A]: {:5.precision$}",
+            self.power,
+            Reading(self.voltage),
+            Reading(self.current),
+        )
+    }
+}
+
+impl fmt::Display for RegulationMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RegulationMode::ConstantVoltage => write!(f, "CV"),
+            RegulationMode::ConstantCurrent => write!(f, "CC"),
+        }
+    }
+}
+
+impl fmt::Display for OutputState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let precision = f.precision().unwrap_or(3);
+        write!(
+            f,
+            "Output: {:?}, Mode: {}, Voltage[V]: {:5.precision$}, Current[A]: {:5.precision$}",
+            self.power,
+            self.mode,
+            Reading(self.voltage),
+            Reading(self.current),
+        )
+    }
+}
+
+impl fmt::Display for FullStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let precision = f.precision().unwrap_or(3);
+        write!(
+            f,
+            "Output: {:?}, Mode: {}, Voltage[V]: {:5.precision$} (set {:5.precision$}), \
+             Current[A]: {:5.precision$} (set {:5.precision$})",
+            self.power,
+            self.mode,
+            Reading(self.voltage),
+            Reading(self.voltage_setpoint),
+            Reading(self.current),
+            Reading(self.current_setpoint),
+        )
+    }
+}
+
+impl fmt::Display for RegulationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Mode: {}, Voltage error[V]: {:+.3}, Current error[A]: {:+.3}",
+            self.mode,
+            Reading(self.voltage_error),
+            Reading(self.current_error),
+        )
+    }
+}
+
+impl fmt::Display for DeviceInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "DHCP:       {:?}\n\
+             IP Address: {}\n\
+             Netmask:    {}\n\
+             Gateway:    {}\n\
+             MAC:        {}\n\
+             UDP Port:   {}\n\
+             Baudrate:   {}",
+            self.dhcp, self.ip, self.netmask, self.gateway, self.mac, self.port, self.baud
+        )
+    }
+}
+
+impl Add for Voltage {
+    type Output = Voltage;
+
+    fn add(self, rhs: Voltage) -> Voltage {
+        Voltage(self.0 + rhs.0)
+    }
+}
+
+impl Sub for Voltage {
+    type Output = Voltage;
+
+    fn sub(self, rhs: Voltage) -> Voltage {
+        Voltage(self.0 - rhs.0)
+    }
+}
+
+impl Add for Current {
+    type Output = Current;
+
+    fn add(self, rhs: Current) -> Current {
+        Current(self.0 + rhs.0)
+    }
+}
+
+impl Sub for Current {
+    type Output = Current;
+
+    fn sub(self, rhs: Current) -> Current {
+        Current(self.0 - rhs.0)
+    }
+}
+
+fn parse_single_value<T: FromStr>(bytes: &[u8]) -> Result<T, ResponseError> {
+    let response = String::from_utf8_lossy(bytes);
+    let value = strip_echo(&response);
+    if value.trim().is_empty() {
+        return Err(ResponseError::Incomplete);
+    }
+    value
+        .strip_suffix('\n')
+        .ok_or(ResponseError::Incomplete)?
+        .parse()
+        .map_err(|_| ResponseError::Invalid)
+}
+
+/// Some firmware echoes the query (e.g. `VSET01?`) before the value, so drop such a leading line
+fn strip_echo(response: &str) -> &str {
+    match response.split_once('\n') {
+        Some((first, rest)) if first.ends_with('?') => rest,
+        _ => response,
+    }
+}
+
+fn parse_next_token<'a, I, T>(iter: &mut I) -> Result<T, ResponseError>
+where
+    I: Iterator<Item = &'a str>,
+    T: FromStr,
+{
+    iter.next()
+        .ok_or(ResponseError::Incomplete)?
+        .parse()
+        .map_err(|_| ResponseError::Invalid)
+}
+
+/// Splits a response to a multi-value query into its lines, to be parsed value by value
+///
+/// Only newline terminated lines are considered, with surrounding whitespace (e.g. a carriage
+/// return) trimmed. Blank lines and echoed queries (ending with `?`) are skipped.
+///
+/// ```
+/// use kwr103::{command::ResponseFramer, ResponseError};
+///
+/// let mut framer = ResponseFramer::new(b"1\r\nPORT:18190\r\n");
+/// assert_eq!(framer.next_value::<u8>().unwrap(), 1);
+/// assert_eq!(framer.next_prefixed::<u16>("PORT:").unwrap(), 18190);
+/// assert!(matches!(framer.next_value::<u8>(), Err(ResponseError::Incomplete)));
+/// ```
+pub struct ResponseFramer {
+    lines: std::vec::IntoIter<String>,
+}
+
+impl ResponseFramer {
+    /// Split `bytes` into response lines
+    pub fn new(bytes: &[u8]) -> Self {
+        let response = String::from_utf8_lossy(bytes);
+        let lines: Vec<String> = response
+            .split_inclusive('\n')
+            .filter(|line| line.ends_with('\n'))
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.ends_with('?'))
+            .map(String::from)
+            .collect();
+        Self {
+            lines: lines.into_iter(),
+        }
+    }
+
+    /// Parse the next line as a value
+    ///
+    /// Fails with [`ResponseError::Incomplete`] if all lines are consumed and with
+    /// [`ResponseError::Invalid`] if the line does not parse.
+    pub fn next_value<T: FromStr>(&mut self) -> Result<T, ResponseError> {
+        self.next_line()?
+            .parse()
+            .map_err(|_| ResponseError::Invalid)
+    }
+
+    /// Parse the next line as a value following `prefix`, e.g. `PORT:18190`
+    ///
+    /// Fails like [`ResponseFramer::next_value`], and with [`ResponseError::Invalid`] if the line
+    /// does not start with `prefix`.
+    pub fn next_prefixed<T: FromStr>(&mut self, prefix: &str) -> Result<T, ResponseError> {
+        self.next_line()?
+            .strip_prefix(prefix)
+            .ok_or(ResponseError::Invalid)?
+            .parse()
+            .map_err(|_| ResponseError::Invalid)
+    }
+
+    fn next_line(&mut self) -> Result<String, ResponseError> {
+        self.lines.next().ok_or(ResponseError::Incomplete)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn framer_yields_values_in_order() {
+        let mut framer = ResponseFramer::new(b"OUT?\n1\r\n\n12.000\nIP:192.168.1.198\n0.5");
+        assert_eq!(framer.next_value::<Switch>().unwrap(), Switch::On);
+        assert_eq!(framer.next_value::<f32>().unwrap(), 12.0);
+        assert_eq!(
+            framer.next_prefixed::<net::Ipv4Addr>("IP:").unwrap(),
+            net::Ipv4Addr::new(192, 168, 1, 198)
+        );
+        assert!(matches!(
+            framer.next_value::<f32>(),
+            Err(ResponseError::Incomplete)
+        ));
+        assert!(matches!(
+            framer.next_prefixed::<u16>("PORT:"),
+            Err(ResponseError::Incomplete)
+        ));
+    }
+
+    #[test]
+    fn framer_rejects_mismatching_lines() {
+        let mut framer = ResponseFramer::new(b"abc\nPORT:18190\nPORT:x\n");
+        assert!(matches!(
+            framer.next_value::<f32>(),
+            Err(ResponseError::Invalid)
+        ));
+        assert!(matches!(
+            framer.next_prefixed::<u16>("BAUDRATE:"),
+            Err(ResponseError::Invalid)
+        ));
+        assert!(matches!(
+            framer.next_prefixed::<u16>("PORT:"),
+            Err(ResponseError::Invalid)
+        ));
+    }
+
+    #[test]
+    fn switch_from_string() {
+        assert_eq!(Ok(Switch::Off), "0".parse());
+        assert_eq!(Ok(Switch::On), "1".parse());
+        assert_eq!(Ok(Switch::Off), "off".parse());
+        assert_eq!(Ok(Switch::On), "on".parse());
+        assert!("2".parse::<Switch>().is_err());
+        assert!("_".parse::<Switch>().is_err());
+    }
+
+    #[test]
+    fn switch_into_u8() {
+        assert_eq!(0u8, Switch::Off as u8);
+        assert_eq!(1u8, Switch::On as u8);
+    }
+
+    #[test]
+    fn switch_bool_conversions() {
+        assert_eq!(Switch::from(true), Switch::On);
+        assert_eq!(Switch::from(false), Switch::Off);
+        assert!(bool::from(Switch::On));
+        assert!(!bool::from(Switch::Off));
+        assert!(Switch::On.is_on());
+        assert!(!Switch::Off.is_on());
+    }
+
+    #[test]
+    fn query_voltage() {
+        assert_eq!(
+            <Voltage as Query>::serialize(DeviceId::Id(2)),
+            "VSET02?\n".as_bytes()
+        );
+        assert_eq!(
+            <Voltage as Query>::serialize(DeviceId::None),
+            "VSET?\n".as_bytes()
+        );
+        assert_eq!(
+            <Voltage as Query>::parse("42.123\n".as_bytes()).unwrap(),
+            Voltage(42.123)
+        );
+    }
+
+    #[test]
+    fn query_voltage_with_echo() {
+        assert_eq!(
+            <Voltage as Query>::parse("VSET01?\n42.0\n".as_bytes()).unwrap(),
+            Voltage(42.0)
+        );
+        assert_eq!(
+            <Output as Query>::parse("OUT?\n1\n".as_bytes()).unwrap(),
+            Output(Switch::On)
+        );
+    }
+
+    #[test]
     fn command_voltage() {
         assert_eq!(
-            Command::serialize(Voltage(42.123), Some(2)),
-            "VSET02:42.123\n".as_bytes()
+            Command::serialize(Voltage(42.123), DeviceId::Id(2)),
+            "VSET02:42.123\n".as_bytes()
+        );
+        assert_eq!(
+            Command::serialize(Voltage(42.123), DeviceId::None),
+            "VSET:42.123\n".as_bytes()
+        );
+    }
+
+    #[test]
+    fn command_voltage_broadcast() {
+        assert_eq!(
+            Command::serialize(Voltage(12.0), DeviceId::Broadcast),
+            "VSET00:12.000\n".as_bytes()
+        );
+        assert_eq!(
+            <Status as Query>::serialize(DeviceId::Broadcast),
+            "OUT00?\nVOUT00?\nIOUT00?\n".as_bytes()
+        );
+    }
+
+    #[test]
+    fn voltage_comparison_and_arithmetic() {
+        assert!(Voltage(12.0) > Voltage(10.0));
+        assert!(Voltage(5.0) <= Voltage(5.0));
+        assert_eq!(Voltage(12.0) + Voltage(0.5), Voltage(12.5));
+        assert_eq!(Voltage(12.0) - Voltage(2.0), Voltage(10.0));
+    }
+
+    #[test]
+    fn query_current() {
+        assert_eq!(
+            <Current as Query>::serialize(DeviceId::Id(2)),
+            "ISET02?\n".as_bytes()
+        );
+        assert_eq!(
+            <Current as Query>::serialize(DeviceId::None),
+            "ISET?\n".as_bytes()
+        );
+        assert_eq!(
+            <Current as Query>::parse("2.123\n".as_bytes()).unwrap(),
+            Current(2.123)
+        );
+    }
+
+    #[test]
+    fn command_current() {
+        assert_eq!(
+            Command::serialize(Current(2.001), DeviceId::Id(2)),
+            "ISET02:2.001\n".as_bytes()
+        );
+        assert_eq!(
+            Command::serialize(Current(2.001), DeviceId::None),
+            "ISET:2.001\n".as_bytes()
+        );
+    }
+
+    #[test]
+    fn current_comparison_and_arithmetic() {
+        assert!(Current(0.5) < Current(1.0));
+        assert!(Current(2.0) >= Current(2.0));
+        assert_eq!(Current(1.5) + Current(0.25), Current(1.75));
+        assert_eq!(Current(1.5) - Current(0.5), Current(1.0));
+    }
+
+    #[test]
+    fn query_voltage_slew() {
+        assert_eq!(
+            <VoltageSlew as Query>::serialize(DeviceId::Id(2)),
+            "VSLEW02?\n".as_bytes()
+        );
+        assert_eq!(
+            <VoltageSlew as Query>::serialize(DeviceId::None),
+            "VSLEW?\n".as_bytes()
+        );
+        assert_eq!(
+            <VoltageSlew as Query>::parse("0.500\n".as_bytes()).unwrap(),
+            VoltageSlew(0.5)
+        );
+    }
+
+    #[test]
+    fn command_voltage_slew() {
+        assert_eq!(
+            Command::serialize(VoltageSlew(1.25), DeviceId::Id(2)),
+            "VSLEW02:1.250\n".as_bytes()
+        );
+        assert_eq!(
+            Command::serialize(VoltageSlew(1.25), DeviceId::None),
+            "VSLEW:1.250\n".as_bytes()
+        );
+    }
+
+    #[test]
+    fn query_current_slew() {
+        assert_eq!(
+            <CurrentSlew as Query>::serialize(DeviceId::Id(2)),
+            "ISLEW02?\n".as_bytes()
+        );
+        assert_eq!(
+            <CurrentSlew as Query>::serialize(DeviceId::None),
+            "ISLEW?\n".as_bytes()
+        );
+        assert_eq!(
+            <CurrentSlew as Query>::parse("0.100\n".as_bytes()).unwrap(),
+            CurrentSlew(0.1)
+        );
+    }
+
+    #[test]
+    fn command_current_slew() {
+        assert_eq!(
+            Command::serialize(CurrentSlew(0.1), DeviceId::Id(2)),
+            "ISLEW02:0.100\n".as_bytes()
+        );
+        assert_eq!(
+            Command::serialize(CurrentSlew(0.1), DeviceId::None),
+            "ISLEW:0.100\n".as_bytes()
+        );
+    }
+
+    #[test]
+    fn query_power_limit() {
+        assert_eq!(
+            <PowerLimit as Query>::serialize(DeviceId::Id(2)),
+            "PSET02?\n".as_bytes()
+        );
+        assert_eq!(
+            <PowerLimit as Query>::serialize(DeviceId::None),
+            "PSET?\n".as_bytes()
+        );
+        assert_eq!(
+            <PowerLimit as Query>::parse("150.000\n".as_bytes()).unwrap(),
+            PowerLimit(150.0)
+        );
+    }
+
+    #[test]
+    fn command_power_limit() {
+        assert_eq!(
+            Command::serialize(PowerLimit(75.5), DeviceId::Id(2)),
+            "PSET02:75.500\n".as_bytes()
+        );
+        assert_eq!(
+            Command::serialize(PowerLimit(75.5), DeviceId::None),
+            "PSET:75.500\n".as_bytes()
+        );
+    }
+
+    #[test]
+    fn query_power() {
+        assert_eq!(
+            <Output as Query>::serialize(DeviceId::Id(2)),
+            "OUT02?\n".as_bytes()
+        );
+        assert_eq!(
+            <Output as Query>::serialize(DeviceId::None),
+            "OUT?\n".as_bytes()
+        );
+        assert_eq!(
+            <Output as Query>::parse("1\n".as_bytes()).unwrap(),
+            Output(Switch::On)
+        );
+        assert_eq!(
+            <Output as Query>::parse("0\n".as_bytes()).unwrap(),
+            Output(Switch::Off)
+        );
+    }
+
+    #[test]
+    fn parse_scientific_notation() {
+        assert_eq!(parse_single_value::<f32>(b"1.234E-01\n").unwrap(), 0.1234);
+        assert_eq!(parse_single_value::<f32>(b"4.2e+01\n").unwrap(), 42.0);
+        assert_eq!(
+            <Current as Query>::parse("ISET?\n1.234E-01\n".as_bytes()).unwrap(),
+            Current(0.1234)
+        );
+        assert_eq!(
+            <Status as Query>::parse("1\n1.200E+01\n1.234E-01\n".as_bytes()).unwrap(),
+            Status {
+                power: Switch::On,
+                voltage: 12.0,
+                current: 0.1234,
+            }
+        );
+        assert_eq!(
+            <FullStatus as Query>::parse("1\n1.2E+01\n-1.5E-03\n1.2E+01\n1E+00\n1\n".as_bytes())
+                .unwrap()
+                .current,
+            -0.0015
+        );
+    }
+
+    #[test]
+    fn setting_provides_command_and_query() {
+        #[derive(Debug, PartialEq)]
+        struct Delay(f32);
+
+        analog_setting!(Delay, "DLY");
+
+        assert_eq!(
+            Command::serialize(Delay(12.0), DeviceId::Id(1)),
+            "DLY01:12.000\n".as_bytes()
+        );
+        assert_eq!(
+            Command::serialize(Delay(0.5), DeviceId::None),
+            "DLY:0.500\n".as_bytes()
+        );
+        assert_eq!(
+            <Delay as Query>::serialize(DeviceId::Id(1)),
+            "DLY01?\n".as_bytes()
+        );
+        assert_eq!(
+            <Delay as Query>::serialize(DeviceId::None),
+            "DLY?\n".as_bytes()
+        );
+        assert_eq!(
+            <Delay as Query>::parse("DLY01?\n0.500\n".as_bytes()).unwrap(),
+            Delay(0.5)
+        );
+        assert!(<Delay as Query>::parse("garbage\n".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn over_voltage_protection_setting() {
+        assert_eq!(
+            Command::serialize(OverVoltageProtection(33.5), DeviceId::Id(2)),
+            "OVP02:33.500\n".as_bytes()
+        );
+        assert_eq!(
+            <OverVoltageProtection as Query>::serialize(DeviceId::None),
+            "OVP?\n".as_bytes()
+        );
+        assert_eq!(
+            <OverVoltageProtection as Query>::parse("OVP?\n33.500\n".as_bytes()).unwrap(),
+            OverVoltageProtection(33.5)
+        );
+    }
+
+    #[test]
+    fn over_current_protection_setting() {
+        assert_eq!(
+            Command::serialize(OverCurrentProtection(1.25), DeviceId::None),
+            "OCP:1.250\n".as_bytes()
+        );
+        assert_eq!(
+            <OverCurrentProtection as Query>::serialize(DeviceId::Broadcast),
+            "OCP00?\n".as_bytes()
+        );
+        assert_eq!(
+            <OverCurrentProtection as Query>::parse("1.250\n".as_bytes()).unwrap(),
+            OverCurrentProtection(1.25)
+        );
+        assert!(<OverCurrentProtection as Query>::parse("high\n".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn command_output() {
+        assert_eq!(
+            Command::serialize(Output(Switch::On), DeviceId::Id(2)),
+            "OUT02:1\n".as_bytes()
+        );
+        assert_eq!(
+            Command::serialize(Output(Switch::Off), DeviceId::Id(2)),
+            "OUT02:0\n".as_bytes()
+        );
+        assert_eq!(
+            Command::serialize(Output(Switch::On), DeviceId::None),
+            "OUT:1\n".as_bytes()
+        );
+        assert_eq!(
+            Command::serialize(Output(Switch::Off), DeviceId::None),
+            "OUT:0\n".as_bytes()
+        );
+    }
+
+    #[test]
+    fn query_output() {
+        assert_eq!(
+            <Status as Query>::serialize(DeviceId::Id(2)),
+            "OUT02?\nVOUT02?\nIOUT02?\n".as_bytes()
+        );
+        assert_eq!(
+            <Status as Query>::serialize(DeviceId::None),
+            "OUT?\nVOUT?\nIOUT?\n".as_bytes()
+        );
+        assert_eq!(
+            <Status as Query>::parse("1\n1.234\n5.678\n".as_bytes()).unwrap(),
+            Status {
+                power: Switch::On,
+                voltage: 1.234,
+                current: 5.678,
+            }
+        );
+    }
+
+    #[test]
+    fn query_response_lines() {
+        assert_eq!(<Voltage as Query>::RESPONSE_LINES, 1);
+        assert_eq!(<Current as Query>::RESPONSE_LINES, 1);
+        assert_eq!(<Output as Query>::RESPONSE_LINES, 1);
+        assert_eq!(<Dhcp as Query>::RESPONSE_LINES, 1);
+        assert_eq!(<Status as Query>::RESPONSE_LINES, 3);
+        assert_eq!(<DeviceInfo as Query>::RESPONSE_LINES, 7);
+    }
+
+    #[test]
+    fn query_mode() {
+        assert_eq!(
+            <Mode as Query>::serialize(DeviceId::Id(2)),
+            "STATUS02?\n".as_bytes()
+        );
+        assert_eq!(
+            <Mode as Query>::serialize(DeviceId::None),
+            "STATUS?\n".as_bytes()
+        );
+        assert_eq!(
+            <Mode as Query>::parse("81\n".as_bytes()).unwrap(),
+            Mode(RegulationMode::ConstantVoltage)
+        );
+        assert_eq!(
+            <Mode as Query>::parse("80\n".as_bytes()).unwrap(),
+            Mode(RegulationMode::ConstantCurrent)
+        );
+    }
+
+    #[test]
+    fn try_from_response_bytes() {
+        let status = Status::try_from(&b"1\n12.000\n0.500\n"[..]).unwrap();
+        assert_eq!(status.voltage, 12.0);
+        assert!(matches!(
+            Status::try_from(&b"1\n12.000\n"[..]),
+            Err(ResponseError::Incomplete)
+        ));
+
+        let info = "DHCP:1\nIP:192.168.1.198\nNETMASK:255.255.255.0\nGateWay:192.168.1.1\nMAC:88-06-00-00-ff-ff\nPORT:18190\nBAUDRATE:115200\n";
+        assert_eq!(DeviceInfo::try_from(info.as_bytes()).unwrap().port, 18190);
+        assert!(matches!(
+            DeviceInfo::try_from(&b"DHCP:1\nIP:garbage\n"[..]),
+            Err(ResponseError::Invalid)
+        ));
+
+        assert_eq!(Voltage::try_from(&b"12.000\n"[..]).unwrap(), Voltage(12.0));
+        assert!(Voltage::try_from(&b"12.0V\n"[..]).is_err());
+
+        assert_eq!(Current::try_from(&b"1.500\n"[..]).unwrap(), Current(1.5));
+        assert!(Current::try_from(&b""[..]).is_err());
+    }
+
+    #[test]
+    fn query_output_state() {
+        assert_eq!(
+            <OutputState as Query>::serialize(DeviceId::Id(2)),
+            "OUT02?\nSTATUS02?\nVOUT02?\nIOUT02?\n".as_bytes()
+        );
+        assert_eq!(OutputState::RESPONSE_LINES, 4);
+
+        let state = <OutputState as Query>::parse("1\n0\n4.210\n1.000\n".as_bytes()).unwrap();
+        assert_eq!(
+            state,
+            OutputState {
+                power: Switch::On,
+                mode: RegulationMode::ConstantCurrent,
+                voltage: 4.21,
+                current: 1.0,
+            }
+        );
+        assert_eq!(
+            state.to_string(),
+            "Output: On, Mode: CC, Voltage[V]: 4.210, Current[A]: 1.000"
+        );
+        assert!(matches!(
+            <OutputState as Query>::parse("1\n0\n4.210\n".as_bytes()),
+            Err(ResponseError::Incomplete)
+        ));
+    }
+
+    #[test]
+    fn query_full_status() {
+        assert_eq!(
+            <FullStatus as Query>::serialize(DeviceId::Id(2)),
+            "OUT02?\nVOUT02?\nIOUT02?\nVSET02?\nISET02?\nSTATUS02?\n".as_bytes()
+        );
+        assert_eq!(
+            <FullStatus as Query>::serialize(DeviceId::None),
+            "OUT?\nVOUT?\nIOUT?\nVSET?\nISET?\nSTATUS?\n".as_bytes()
+        );
+
+        let status =
+            <FullStatus as Query>::parse("1\n11.998\n0.131\n12.000\n1.000\n1\n".as_bytes())
+                .unwrap();
+        assert_eq!(
+            status,
+            FullStatus {
+                power: Switch::On,
+                voltage: 11.998,
+                current: 0.131,
+                voltage_setpoint: 12.0,
+                current_setpoint: 1.0,
+                mode: RegulationMode::ConstantVoltage,
+            }
+        );
+        assert_eq!(
+            status.to_string(),
+            "Output: On, Mode: CV, Voltage[V]: 11.998 (set 12.000), Current[A]: 0.131 (set 1.000)"
+        );
+    }
+
+    #[test]
+    fn full_status_display_precision() {
+        let status = FullStatus {
+            power: Switch::On,
+            voltage: 3.3,
+            current: 0.0123,
+            voltage_setpoint: 3.3,
+            current_setpoint: 0.5,
+            mode: RegulationMode::ConstantVoltage,
+        };
+        assert_eq!(
+            format!("{:.4}", status),
+            "Output: On, Mode: CV, Voltage[V]: 3.3000 (set 3.3000), Current[A]: 0.0123 (set 0.5000)"
+        );
+    }
+
+    #[test]
+    fn mac_address_formats() {
+        let dashed: MacAddress = "88-06-00-00-ff-ff".parse().unwrap();
+        let colon: MacAddress = "88:06:00:00:FF:FF".parse().unwrap();
+        assert_eq!(dashed, colon);
+        assert_eq!(dashed.to_string(), "88:06:00:00:ff:ff");
+
+        assert!("88-06-00-00-ff".parse::<MacAddress>().is_err());
+        assert!("88-06-00-00-ff-ff-00".parse::<MacAddress>().is_err());
+        assert!("88-06-00-00-ff-zz".parse::<MacAddress>().is_err());
+        assert!("88-06-00:00-ff-ff".parse::<MacAddress>().is_err());
+        assert!("88-6-00-00-ff-fff".parse::<MacAddress>().is_err());
+        assert!("88-06-00-00-+f-ff".parse::<MacAddress>().is_err());
+        assert!("88--06-00-00-ff".parse::<MacAddress>().is_err());
+    }
+
+    #[test]
+    fn query_deviceinfo() {
+        assert_eq!(
+            <DeviceInfo as Query>::serialize(DeviceId::Id(2)),
+            ":SYST:DEVINFO?\n".as_bytes()
+        );
+        assert_eq!(
+            <DeviceInfo as Query>::serialize(DeviceId::None),
+            ":SYST:DEVINFO?\n".as_bytes()
+        );
+
+        let response = "DHCP:0\nIP:192.168.1.198\nNETMASK:255.255.255.0\nGateWay:192.168.1.1\nMAC:88-06-00-00-ff-ff\nPORT:18190\nBAUDRATE:115200\n".as_bytes();
+        assert_eq!(
+            <DeviceInfo as Query>::parse(response).unwrap(),
+            DeviceInfo {
+                dhcp: Switch::Off,
+                ip: net::Ipv4Addr::new(192, 168, 1, 198),
+                netmask: net::Ipv4Addr::new(255, 255, 255, 0),
+                gateway: net::Ipv4Addr::new(192, 168, 1, 1),
+                mac: MacAddress([0x88, 0x06, 0x00, 0x00, 0xff, 0xff]),
+                port: 18190,
+                baud: BaudRate::B115200,
+            }
+        );
+
+        let corrupted = "DHCP:0\nIP:192.168.1.198\nNETMASK:255.255.255.0\nGateWay:192.168.1.1\nMAC:88-06-0-00-ff\nPORT:18190\nBAUDRATE:115200\n".as_bytes();
+        assert!(matches!(
+            <DeviceInfo as Query>::parse(corrupted),
+            Err(ResponseError::Invalid)
+        ));
+
+        let unsupported_baud = "DHCP:0\nIP:192.168.1.198\nNETMASK:255.255.255.0\nGateWay:192.168.1.1\nMAC:88-06-00-00-ff-ff\nPORT:18190\nBAUDRATE:4800\n".as_bytes();
+        assert!(matches!(
+            <DeviceInfo as Query>::parse(unsupported_baud),
+            Err(ResponseError::Invalid)
+        ));
+    }
+
+    #[test]
+    fn command_dhcp() {
+        assert_eq!(
+            Command::serialize(Dhcp(Switch::On), DeviceId::Id(2)),
+            ":SYST:DHCP 1\n".as_bytes()
+        );
+        assert_eq!(
+            Command::serialize(Dhcp(Switch::Off), DeviceId::Id(2)),
+            ":SYST:DHCP 0\n".as_bytes()
+        );
+        assert_eq!(
+            Command::serialize(Dhcp(Switch::On), DeviceId::None),
+            ":SYST:DHCP 1\n".as_bytes()
+        );
+        assert_eq!(
+            Command::serialize(Dhcp(Switch::Off), DeviceId::None),
+            ":SYST:DHCP 0\n".as_bytes()
+        );
+    }
+
+    #[test]
+    fn command_udp_port() {
+        assert_eq!(
+            Command::serialize(UdpPort(18191), DeviceId::Id(2)),
+            ":SYST:PORT 18191\n".as_bytes()
+        );
+        assert!(UdpPort(18191).validate().is_ok());
+        assert!(UdpPort(0).validate().is_err());
+    }
+
+    #[test]
+    fn command_display_brightness() {
+        assert_eq!(
+            Command::serialize(DisplayBrightness(40), DeviceId::Id(2)),
+            ":SYST:BRIGHT 40\n".as_bytes()
+        );
+        assert!(DisplayBrightness(0).validate().is_ok());
+        assert!(DisplayBrightness(100).validate().is_ok());
+        assert!(DisplayBrightness(101)
+            .validate()
+            .is_err_and(|e| matches!(e, TransactionError::InvalidConfiguration(_))));
+    }
+
+    #[test]
+    fn query_display_brightness() {
+        assert_eq!(
+            <DisplayBrightness as Query>::serialize(DeviceId::Id(2)),
+            ":SYST:BRIGHT?\n".as_bytes()
+        );
+        assert_eq!(
+            <DisplayBrightness as Query>::parse("75\n".as_bytes()).unwrap(),
+            DisplayBrightness(75)
+        );
+        assert!(<DisplayBrightness as Query>::parse("150\n".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn query_udp_port() {
+        assert_eq!(
+            <UdpPort as Query>::serialize(DeviceId::Id(2)),
+            ":SYST:PORT?\n".as_bytes()
+        );
+        assert_eq!(
+            <UdpPort as Query>::parse("18190\n".as_bytes()).unwrap(),
+            UdpPort(18190)
+        );
+        assert!(<UdpPort as Query>::parse("65536\n".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn query_dhcp() {
+        assert_eq!(
+            <Dhcp as Query>::serialize(DeviceId::Id(2)),
+            ":SYST:DHCP?\n".as_bytes()
         );
         assert_eq!(
-            Command::serialize(Voltage(42.123), None),
-            "VSET:42.123\n".as_bytes()
+            <Dhcp as Query>::serialize(DeviceId::None),
+            ":SYST:DHCP?\n".as_bytes()
+        );
+        assert_eq!(
+            <Dhcp as Query>::parse("1\n".as_bytes()).unwrap(),
+            Dhcp(Switch::On)
         );
     }
 
     #[test]
-    fn query_current() {
+    fn command_remote_control() {
         assert_eq!(
-            <Current as Query>::serialize(Some(2)),
-            "ISET02?\n".as_bytes()
+            Command::serialize(RemoteControl(Switch::On), DeviceId::Id(2)),
+            ":SYST:LOCK 1\n".as_bytes()
         );
-        assert_eq!(<Current as Query>::serialize(None), "ISET?\n".as_bytes());
         assert_eq!(
-            <Current as Query>::parse("2.123\n".as_bytes()).unwrap(),
-            Current(2.123)
+            Command::serialize(RemoteControl(Switch::Off), DeviceId::None),
+            ":SYST:LOCK 0\n".as_bytes()
         );
     }
 
     #[test]
-    fn command_current() {
+    fn query_remote_control() {
         assert_eq!(
-            Command::serialize(Current(2.001), Some(2)),
-            "ISET02:2.001\n".as_bytes()
+            <RemoteControl as Query>::serialize(DeviceId::Id(2)),
+            ":SYST:LOCK?\n".as_bytes()
         );
         assert_eq!(
-            Command::serialize(Current(2.001), None),
-            "ISET:2.001\n".as_bytes()
+            <RemoteControl as Query>::serialize(DeviceId::None),
+            ":SYST:LOCK?\n".as_bytes()
+        );
+        assert_eq!(
+            <RemoteControl as Query>::parse("1\n".as_bytes()).unwrap(),
+            RemoteControl(Switch::On)
+        );
+        assert_eq!(
+            <RemoteControl as Query>::parse("0\n".as_bytes()).unwrap(),
+            RemoteControl(Switch::Off)
+        );
+        assert_eq!(
+            <RemoteControl as Query>::parse("REMOTE\r\n".as_bytes()).unwrap(),
+            RemoteControl(Switch::On)
+        );
+        assert_eq!(
+            <RemoteControl as Query>::parse("LOCAL\n".as_bytes()).unwrap(),
+            RemoteControl(Switch::Off)
         );
+        assert!(<RemoteControl as Query>::parse("2\n".as_bytes()).is_err());
     }
 
     #[test]
-    fn query_power() {
-        assert_eq!(<Output as Query>::serialize(Some(2)), "OUT02?\n".as_bytes());
-        assert_eq!(<Output as Query>::serialize(None), "OUT?\n".as_bytes());
+    fn command_bus_termination() {
         assert_eq!(
-            <Output as Query>::parse("1\n".as_bytes()).unwrap(),
-            Output(Switch::On)
+            Command::serialize(BusTermination(Switch::On), DeviceId::Id(2)),
+            "TERM02:1\n".as_bytes()
         );
         assert_eq!(
-            <Output as Query>::parse("0\n".as_bytes()).unwrap(),
-            Output(Switch::Off)
+            Command::serialize(BusTermination(Switch::Off), DeviceId::None),
+            "TERM:0\n".as_bytes()
         );
     }
 
     #[test]
-    fn command_output() {
+    fn query_bus_termination() {
         assert_eq!(
-            Command::serialize(Output(Switch::On), Some(2)),
-            "OUT02:1\n".as_bytes()
+            <BusTermination as Query>::serialize(DeviceId::Id(2)),
+            "TERM02?\n".as_bytes()
         );
         assert_eq!(
-            Command::serialize(Output(Switch::Off), Some(2)),
-            "OUT02:0\n".as_bytes()
+            <BusTermination as Query>::parse("1\n".as_bytes()).unwrap(),
+            BusTermination(Switch::On)
         );
         assert_eq!(
-            Command::serialize(Output(Switch::On), None),
-            "OUT:1\n".as_bytes()
+            <BusTermination as Query>::parse("0\n".as_bytes()).unwrap(),
+            BusTermination(Switch::Off)
         );
+        assert!(<BusTermination as Query>::parse("2\n".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn query_sense_fault() {
         assert_eq!(
-            Command::serialize(Output(Switch::Off), None),
-            "OUT:0\n".as_bytes()
+            <SenseFault as Query>::serialize(DeviceId::Id(2)),
+            "SENSEFAULT02?\n".as_bytes()
+        );
+        assert_eq!(
+            <SenseFault as Query>::serialize(DeviceId::None),
+            "SENSEFAULT?\n".as_bytes()
+        );
+        assert_eq!(
+            <SenseFault as Query>::parse("1\n".as_bytes()).unwrap(),
+            SenseFault(true)
+        );
+        assert_eq!(
+            <SenseFault as Query>::parse("0\n".as_bytes()).unwrap(),
+            SenseFault(false)
+        );
+        assert_eq!(
+            <SenseFault as Query>::parse("SENSEFAULT?\n1\n".as_bytes()).unwrap(),
+            SenseFault(true)
         );
+        assert!(<SenseFault as Query>::parse("2\n".as_bytes()).is_err());
     }
 
     #[test]
-    fn query_output() {
+    fn query_panel_activity() {
         assert_eq!(
-            <Status as Query>::serialize(Some(2)),
-            "OUT02?\nVOUT02?\nIOUT02?\n".as_bytes()
+            <PanelActivity as Query>::serialize(DeviceId::Id(2)),
+            "PANEL02?\n".as_bytes()
         );
         assert_eq!(
-            <Status as Query>::serialize(None),
-            "OUT?\nVOUT?\nIOUT?\n".as_bytes()
+            <PanelActivity as Query>::parse("1\n".as_bytes()).unwrap(),
+            PanelActivity(true)
         );
         assert_eq!(
-            <Status as Query>::parse("1\n1.234\n5.678\n".as_bytes()).unwrap(),
-            Status {
-                power: Switch::On,
-                voltage: 1.234,
-                current: 5.678,
-            }
+            <PanelActivity as Query>::parse("PANEL01?\n0\n".as_bytes()).unwrap(),
+            PanelActivity(false)
         );
+        assert!(matches!(
+            <PanelActivity as Query>::parse(b"\xf8ERR\n"),
+            Err(ResponseError::Invalid)
+        ));
     }
 
     #[test]
-    fn query_deviceinfo() {
+    fn query_output_on_time() {
         assert_eq!(
-            <DeviceInfo as Query>::serialize(Some(2)),
-            ":SYST:DEVINFO?\n".as_bytes()
+            <OutputOnTime as Query>::serialize(DeviceId::Id(2)),
+            "OUTTIME02?\n".as_bytes()
         );
         assert_eq!(
-            <DeviceInfo as Query>::serialize(None),
-            ":SYST:DEVINFO?\n".as_bytes()
+            <OutputOnTime as Query>::serialize(DeviceId::None),
+            "OUTTIME?\n".as_bytes()
         );
+        assert_eq!(
+            <OutputOnTime as Query>::parse("3600\n".as_bytes()).unwrap(),
+            OutputOnTime(Duration::from_secs(3600))
+        );
+        assert!(matches!(
+            <OutputOnTime as Query>::parse("-1\n".as_bytes()),
+            Err(ResponseError::Invalid)
+        ));
+    }
 
-        let response = "DHCP:0\nIP:192.168.1.198\nNETMASK:255.255.255.0\nGateWay:192.168.1.1\nMAC:88-06-00-00-ff-ff\nPORT:18190\nBAUDRATE:115200\n".as_bytes();
+    #[test]
+    fn command_auto_off_timer() {
         assert_eq!(
-            <DeviceInfo as Query>::parse(response).unwrap(),
-            DeviceInfo {
-                dhcp: Switch::Off,
-                ip: net::Ipv4Addr::new(192, 168, 1, 198),
-                netmask: net::Ipv4Addr::new(255, 255, 255, 0),
-                gateway: net::Ipv4Addr::new(192, 168, 1, 1),
-                mac: String::from("88-06-00-00-ff-ff"),
-                port: 18190,
-                baud: 115200,
+            Command::serialize(
+                AutoOffTimer(Some(Duration::from_secs(3600))),
+                DeviceId::Id(2)
+            ),
+            "OFFTIME02:3600\n".as_bytes()
+        );
+        assert_eq!(
+            Command::serialize(AutoOffTimer(None), DeviceId::None),
+            "OFFTIME:0\n".as_bytes()
+        );
+        assert!(AutoOffTimer(None).validate().is_ok());
+        assert!(AutoOffTimer(Some(Duration::from_millis(1500)))
+            .validate()
+            .is_ok());
+        assert!(AutoOffTimer(Some(Duration::from_millis(500)))
+            .validate()
+            .is_err());
+    }
+
+    #[test]
+    fn query_auto_off_timer() {
+        assert_eq!(
+            <AutoOffTimer as Query>::serialize(DeviceId::Id(2)),
+            "OFFTIME02?\n".as_bytes()
+        );
+        assert_eq!(
+            <AutoOffTimer as Query>::parse("90\n".as_bytes()).unwrap(),
+            AutoOffTimer(Some(Duration::from_secs(90)))
+        );
+        assert_eq!(
+            <AutoOffTimer as Query>::parse("0\n".as_bytes()).unwrap(),
+            AutoOffTimer(None)
+        );
+        assert!(<AutoOffTimer as Query>::parse("-1\n".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn query_input_voltage() {
+        assert_eq!(
+            <InputVoltage as Query>::serialize(DeviceId::Id(2)),
+            "VIN02?\n".as_bytes()
+        );
+        assert_eq!(
+            <InputVoltage as Query>::serialize(DeviceId::None),
+            "VIN?\n".as_bytes()
+        );
+        assert_eq!(
+            <InputVoltage as Query>::parse("229.4\n".as_bytes()).unwrap(),
+            InputVoltage(229.4)
+        );
+        assert!(matches!(
+            <InputVoltage as Query>::parse("ERR\n".as_bytes()),
+            Err(ResponseError::Invalid)
+        ));
+        assert!(matches!(
+            <InputVoltage as Query>::parse("-5.0\n".as_bytes()),
+            Err(ResponseError::Invalid)
+        ));
+    }
+
+    #[test]
+    fn query_temperature() {
+        assert_eq!(
+            <Temperature as Query>::serialize(DeviceId::Id(2)),
+            "TEMP02?\n".as_bytes()
+        );
+        assert_eq!(
+            <Temperature as Query>::parse("41.5\n".as_bytes()).unwrap(),
+            Temperature(41.5)
+        );
+        assert!(matches!(
+            <Temperature as Query>::parse("ERR\n".as_bytes()),
+            Err(ResponseError::Invalid)
+        ));
+    }
+
+    #[test]
+    fn verify_voltage_readback() {
+        assert!(Voltage(12.0).matches(&Voltage(12.0)));
+        assert!(Voltage(12.0).matches(&Voltage(12.004)));
+        assert!(!Voltage(12.0).matches(&Voltage(12.01)));
+        assert!(!Voltage(12.0).matches(&Voltage(0.0)));
+    }
+
+    #[test]
+    fn verify_current_readback() {
+        assert!(Current(1.5).matches(&Current(1.5)));
+        assert!(Current(1.5).matches(&Current(1.5004)));
+        assert!(!Current(1.5).matches(&Current(1.501)));
+    }
+
+    #[test]
+    fn verify_output_readback() {
+        assert!(Output(Switch::On).matches(&Output(Switch::On)));
+        assert!(!Output(Switch::On).matches(&Output(Switch::Off)));
+        assert!(Output(Switch::Off).matches(&Output(Switch::Off)));
+    }
+
+    #[test]
+    fn regulation_report_from_full_status() {
+        let status = FullStatus {
+            power: Switch::On,
+            voltage: 4.2,
+            current: 1.0,
+            voltage_setpoint: 12.0,
+            current_setpoint: 1.0,
+            mode: RegulationMode::ConstantCurrent,
+        };
+        let report = RegulationReport::from(&status);
+        assert!((report.voltage_error + 7.8).abs() < 1e-6);
+        assert_eq!(report.current_error, 0.0);
+        assert_eq!(report.mode, RegulationMode::ConstantCurrent);
+        assert_eq!(
+            report.to_string(),
+            "Mode: CC, Voltage error[V]: -7.800, Current error[A]: +0.000"
+        );
+    }
+
+    #[test]
+    fn command_trigger_beep() {
+        assert_eq!(
+            Command::serialize(TriggerBeep, DeviceId::Id(2)),
+            ":SYST:BEEP\n".as_bytes()
+        );
+        assert_eq!(
+            Command::serialize(TriggerBeep, DeviceId::None),
+            ":SYST:BEEP\n".as_bytes()
+        );
+    }
+
+    #[test]
+    fn measurement_from_status() {
+        let before = SystemTime::now();
+        let measurement = Measurement::from(&Status {
+            power: Switch::On,
+            voltage: 12.0,
+            current: 0.5,
+        });
+        assert_eq!(measurement.voltage, 12.0);
+        assert_eq!(measurement.current, 0.5);
+        assert_eq!(measurement.power, 6.0);
+        assert!(measurement.timestamp >= before);
+        assert!(measurement.timestamp <= SystemTime::now());
+
+        let measurement = Measurement::new(5.0, 0.0);
+        assert_eq!(measurement.power, 0.0);
+    }
+
+    #[test]
+    fn status_display_precision() {
+        let status = Status {
+            power: Switch::On,
+            voltage: 3.3,
+            current: 0.0123,
+        };
+        assert_eq!(
+            format!("{:.4}", status),
+            "Output: On, Voltage[V]: 3.3000, Current[A]: 0.0123"
+        );
+        assert_eq!(
+            status.to_string(),
+            "Output: On, Voltage[V]: 3.300, Current[A]: 0.012"
+        );
+    }
+
+    #[test]
+    fn status_with_non_finite_measurements() {
+        let status = Status {
+            power: Switch::On,
+            voltage: f32::NAN,
+            current: f32::INFINITY,
+        };
+        assert!(!status.is_valid());
+        assert_eq!(
+            status.to_string(),
+            "Output: On, Voltage[V]:   n/a, Current[A]:   n/a"
+        );
+
+        let status = Status {
+            power: Switch::On,
+            voltage: 12.0,
+            current: 0.5,
+        };
+        assert!(status.is_valid());
+        assert_eq!(
+            status.to_string(),
+            "Output: On, Voltage[V]: 12.000, Current[A]: 0.500"
+        );
+    }
+
+    #[test]
+    fn command_device_name() {
+        let name = DeviceName("Rack 3-B.2".to_string());
+        assert!(name.validate().is_ok());
+        assert_eq!(
+            Command::serialize(name, DeviceId::Id(2)),
+            ":SYST:NAME Rack 3-B.2\n".as_bytes()
+        );
+        assert!(DeviceName("x".repeat(DeviceName::MAX_LEN))
+            .validate()
+            .is_ok());
+    }
+
+    #[test]
+    fn command_device_name_rejects_invalid_labels() {
+        for invalid in ["", "an overly long label", "Rack\n3", "Labör"] {
+            assert!(
+                DeviceName(invalid.to_string())
+                    .validate()
+                    .is_err_and(|e| matches!(e, TransactionError::InvalidConfiguration(_))),
+                "{:?}",
+                invalid
+            );
+        }
+    }
+
+    #[test]
+    fn query_device_name() {
+        assert_eq!(
+            <DeviceName as Query>::serialize(DeviceId::Id(2)),
+            ":SYST:NAME?\n".as_bytes()
+        );
+        assert_eq!(
+            <DeviceName as Query>::parse("Rack 3\r\n".as_bytes()).unwrap(),
+            DeviceName("Rack 3".to_string())
+        );
+    }
+
+    #[test]
+    fn status_approx_eq() {
+        let status = Status {
+            power: Switch::On,
+            voltage: 12.0,
+            current: 0.5,
+        };
+        let jittered = |dv: f32, di: f32| Status {
+            power: Switch::On,
+            voltage: 12.0 + dv,
+            current: 0.5 + di,
+        };
+        assert!(status.approx_eq(&status, 0.0));
+        assert!(status.approx_eq(&jittered(0.009, -0.009), 0.01));
+        assert!(!status.approx_eq(&jittered(0.02, 0.0), 0.01));
+        assert!(!status.approx_eq(&jittered(0.0, -0.02), 0.01));
+
+        let off = Status {
+            power: Switch::Off,
+            ..jittered(0.0, 0.0)
+        };
+        assert!(!status.approx_eq(&off, 0.01));
+        assert!(!status.approx_eq(&jittered(f32::NAN, 0.0), 0.01));
+    }
+
+    #[test]
+    fn query_active_preset() {
+        assert_eq!(
+            <ActivePreset as Query>::serialize(DeviceId::Id(2)),
+            "PRESET02?\n".as_bytes()
+        );
+        assert_eq!(
+            <ActivePreset as Query>::parse("3\n".as_bytes()).unwrap(),
+            ActivePreset(PresetSlot::new(3))
+        );
+        assert_eq!(
+            <ActivePreset as Query>::parse("0\n".as_bytes()).unwrap(),
+            ActivePreset(None)
+        );
+        assert_eq!(
+            <ActivePreset as Query>::parse("NONE\n".as_bytes()).unwrap(),
+            ActivePreset(None)
+        );
+        assert!(<ActivePreset as Query>::parse("6\n".as_bytes()).is_err());
+        assert!(<ActivePreset as Query>::parse("M1\n".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn preset_slot_range() {
+        assert_eq!(PresetSlot::new(0), None);
+        assert_eq!(PresetSlot::new(1).unwrap().number(), 1);
+        assert_eq!(PresetSlot::new(5).unwrap().to_string(), "M5");
+        assert_eq!(PresetSlot::new(6), None);
+    }
+
+    #[test]
+    fn query_panel_state() {
+        assert_eq!(
+            <PanelState as Query>::serialize(DeviceId::Id(2)),
+            ":SYST:LOCK?\n".as_bytes()
+        );
+        assert_eq!(
+            <PanelState as Query>::parse("0\n".as_bytes()).unwrap(),
+            PanelState::Local
+        );
+        assert_eq!(
+            <PanelState as Query>::parse("1\n".as_bytes()).unwrap(),
+            PanelState::RemoteLocked
+        );
+        assert_eq!(
+            <PanelState as Query>::parse("LOCAL\r\n".as_bytes()).unwrap(),
+            PanelState::Local
+        );
+        assert_eq!(
+            <PanelState as Query>::parse(":SYST:LOCK?\nremote\n".as_bytes()).unwrap(),
+            PanelState::RemoteLocked
+        );
+        assert!(<PanelState as Query>::parse("2\n".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn serialize_to_string() {
+        assert_eq!(
+            Command::serialize_str(Voltage(12.0), DeviceId::Id(1)),
+            "VSET01:12.000"
+        );
+        assert_eq!(
+            <Voltage as Query>::serialize_str(DeviceId::Id(1)),
+            "VSET01?"
+        );
+        assert_eq!(
+            <Status as Query>::serialize_str(DeviceId::None),
+            "OUT?\nVOUT?\nIOUT?"
+        );
+    }
+
+    #[test]
+    fn query_fan_status() {
+        assert_eq!(
+            <FanStatus as Query>::serialize(DeviceId::Id(2)),
+            "FAN02?\n".as_bytes()
+        );
+        assert_eq!(
+            <FanStatus as Query>::parse("1,2400\n".as_bytes()).unwrap(),
+            FanStatus {
+                rpm: Some(2400),
+                on: Switch::On
+            }
+        );
+        assert_eq!(
+            <FanStatus as Query>::parse("0 0\n".as_bytes()).unwrap(),
+            FanStatus {
+                rpm: Some(0),
+                on: Switch::Off
+            }
+        );
+        assert_eq!(
+            <FanStatus as Query>::parse("1\n".as_bytes()).unwrap(),
+            FanStatus {
+                rpm: None,
+                on: Switch::On
             }
         );
+        assert!(<FanStatus as Query>::parse("1,fast\n".as_bytes()).is_err());
+        assert!(<FanStatus as Query>::parse("1,2400,3\n".as_bytes()).is_err());
     }
 
     #[test]
-    fn command_dhcp() {
+    fn command_clear_protection() {
         assert_eq!(
-            Command::serialize(Dhcp(Switch::On), Some(2)),
-            ":SYST:DHCP 1\n".as_bytes()
+            Command::serialize(ClearProtection, DeviceId::Id(2)),
+            ":PROT:CLE\n".as_bytes()
         );
         assert_eq!(
-            Command::serialize(Dhcp(Switch::Off), Some(2)),
-            ":SYST:DHCP 0\n".as_bytes()
+            Command::serialize(ClearProtection, DeviceId::None),
+            ":PROT:CLE\n".as_bytes()
         );
+    }
+
+    #[test]
+    fn command_reset() {
         assert_eq!(
-            Command::serialize(Dhcp(Switch::On), None),
-            ":SYST:DHCP 1\n".as_bytes()
+            Command::serialize(Reset, DeviceId::Id(2)),
+            "*RST\n".as_bytes()
         );
         assert_eq!(
-            Command::serialize(Dhcp(Switch::Off), None),
-            ":SYST:DHCP 0\n".as_bytes()
+            Command::serialize(Reset, DeviceId::None),
+            "*RST\n".as_bytes()
         );
     }
 
     #[test]
-    fn query_dhcp() {
+    fn query_self_test() {
         assert_eq!(
-            <Dhcp as Query>::serialize(Some(2)),
-            ":SYST:DHCP?\n".as_bytes()
+            <SelfTest as Query>::serialize(DeviceId::Id(2)),
+            "*TST?\n".as_bytes()
         );
-        assert_eq!(<Dhcp as Query>::serialize(None), ":SYST:DHCP?\n".as_bytes());
         assert_eq!(
-            <Dhcp as Query>::parse("1\n".as_bytes()).unwrap(),
-            Dhcp(Switch::On)
+            <SelfTest as Query>::serialize(DeviceId::None),
+            "*TST?\n".as_bytes()
+        );
+        assert_eq!(
+            <SelfTest as Query>::parse("0\n".as_bytes()).unwrap(),
+            SelfTest(SelfTestResult {
+                passed: true,
+                code: 0
+            })
+        );
+        assert_eq!(
+            <SelfTest as Query>::parse("1\n".as_bytes()).unwrap(),
+            SelfTest(SelfTestResult {
+                passed: false,
+                code: 1
+            })
         );
     }
+
+    #[test]
+    fn ratings_for_model() {
+        assert_eq!(
+            Ratings::for_model("KWR103"),
+            Some(Ratings {
+                max_voltage: 60.0,
+                max_current: 15.0,
+                voltage_resolution: 0.01,
+                current_resolution: 0.001,
+            })
+        );
+        assert_eq!(Ratings::for_model("KA3005P"), None);
+    }
+
+    #[test]
+    fn ratings_round_to_resolution() {
+        let ratings = Ratings::for_model("KORAD KWR103 V1.0.3").unwrap();
+        assert_eq!(ratings.voltage_resolution, 0.01);
+        assert_eq!(ratings.current_resolution, 0.001);
+        assert!((ratings.round_voltage(12.3456) - 12.35).abs() < 1e-6);
+        assert!((ratings.round_voltage(12.344) - 12.34).abs() < 1e-6);
+        assert!((ratings.round_current(1.23449) - 1.234).abs() < 1e-6);
+    }
+
+    #[test]
+    fn query_ratings() {
+        assert_eq!(
+            <Ratings as Query>::serialize(DeviceId::Id(2)),
+            "*IDN?\n".as_bytes()
+        );
+        assert_eq!(
+            <Ratings as Query>::serialize(DeviceId::None),
+            "*IDN?\n".as_bytes()
+        );
+        assert_eq!(
+            <Ratings as Query>::parse("KORAD KWR103 V1.0.3 SN:00000001\n".as_bytes()).unwrap(),
+            Ratings {
+                max_voltage: 60.0,
+                max_current: 15.0,
+                voltage_resolution: 0.01,
+                current_resolution: 0.001,
+            }
+        );
+        assert!(<Ratings as Query>::parse("KORAD KA3005P V5.8\n".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn serialize_is_body_plus_newline() {
+        let id = DeviceId::Id(2);
+        assert_command_body(Voltage(12.0), id);
+        assert_command_body(Current(1.5), id);
+        assert_command_body(VoltageSlew(0.5), id);
+        assert_command_body(CurrentSlew(0.5), id);
+        assert_command_body(PowerLimit(100.0), id);
+        assert_command_body(OverVoltageProtection(65.0), id);
+        assert_command_body(OverCurrentProtection(16.0), id);
+        assert_command_body(Output(Switch::On), id);
+        assert_command_body(Dhcp(Switch::Off), id);
+        assert_command_body(UdpPort(18190), id);
+        assert_command_body(RemoteControl(Switch::On), id);
+        assert_command_body(DeviceName("bench-1".to_string()), id);
+        assert_command_body(ClearProtection, id);
+        assert_command_body(TriggerBeep, id);
+        assert_command_body(Reset, id);
+        assert_command_body(AutoOffTimer(Some(Duration::from_secs(60))), id);
+        assert_command_body(BusTermination(Switch::On), id);
+        assert_command_body(DisplayBrightness(50), id);
+
+        assert_query_body::<Voltage>(id);
+        assert_query_body::<Current>(id);
+        assert_query_body::<VoltageSlew>(id);
+        assert_query_body::<CurrentSlew>(id);
+        assert_query_body::<PowerLimit>(id);
+        assert_query_body::<OverVoltageProtection>(id);
+        assert_query_body::<OverCurrentProtection>(id);
+        assert_query_body::<Output>(id);
+        assert_query_body::<Status>(id);
+        assert_query_body::<Mode>(id);
+        assert_query_body::<FullStatus>(id);
+        assert_query_body::<DeviceInfo>(id);
+        assert_query_body::<Dhcp>(id);
+        assert_query_body::<UdpPort>(id);
+        assert_query_body::<RemoteControl>(id);
+        assert_query_body::<SenseFault>(id);
+        assert_query_body::<PanelActivity>(id);
+        assert_query_body::<OutputOnTime>(id);
+        assert_query_body::<InputVoltage>(id);
+        assert_query_body::<Temperature>(id);
+        assert_query_body::<DeviceName>(id);
+        assert_query_body::<ActivePreset>(id);
+        assert_query_body::<PanelState>(id);
+        assert_query_body::<FanStatus>(id);
+        assert_query_body::<SelfTest>(id);
+        assert_query_body::<Ratings>(id);
+        assert_query_body::<AutoOffTimer>(id);
+        assert_query_body::<OutputState>(id);
+        assert_query_body::<BusTermination>(id);
+        assert_query_body::<DisplayBrightness>(id);
+    }
+
+    fn assert_command_body<C: Command + Clone>(cmd: C, id: DeviceId) {
+        let body = C::serialize_body(cmd.clone(), id);
+        assert!(!body.ends_with(b"\n"));
+        assert_eq!(C::serialize(cmd, id), [body, b"\n".to_vec()].concat());
+    }
+
+    fn assert_query_body<Q: Query>(id: DeviceId) {
+        let body = Q::serialize_body(id);
+        assert!(!body.ends_with(b"\n"));
+        assert_eq!(Q::serialize(id), [body, b"\n".to_vec()].concat());
+    }
+
+    fn assert_incomplete_on_empty<Q: Query + fmt::Debug>() {
+        for response in ["", "\n", " \r\n"] {
+            assert!(
+                matches!(
+                    Q::parse(response.as_bytes()),
+                    Err(ResponseError::Incomplete)
+                ),
+                "{} did not yield Incomplete for {:?}",
+                std::any::type_name::<Q>(),
+                response
+            );
+        }
+    }
+
+    #[test]
+    fn empty_response_is_incomplete() {
+        assert_incomplete_on_empty::<Voltage>();
+        assert_incomplete_on_empty::<Current>();
+        assert_incomplete_on_empty::<VoltageSlew>();
+        assert_incomplete_on_empty::<CurrentSlew>();
+        assert_incomplete_on_empty::<PowerLimit>();
+        assert_incomplete_on_empty::<Output>();
+        assert_incomplete_on_empty::<Status>();
+        assert_incomplete_on_empty::<DeviceInfo>();
+        assert_incomplete_on_empty::<Dhcp>();
+        assert_incomplete_on_empty::<RemoteControl>();
+        assert_incomplete_on_empty::<SelfTest>();
+        assert_incomplete_on_empty::<Ratings>();
+        assert_incomplete_on_empty::<Mode>();
+        assert_incomplete_on_empty::<FullStatus>();
+        assert_incomplete_on_empty::<SenseFault>();
+        assert_incomplete_on_empty::<PanelActivity>();
+        assert_incomplete_on_empty::<OutputOnTime>();
+        assert_incomplete_on_empty::<InputVoltage>();
+        assert_incomplete_on_empty::<AutoOffTimer>();
+        assert_incomplete_on_empty::<OutputState>();
+        assert_incomplete_on_empty::<BusTermination>();
+        assert_incomplete_on_empty::<DisplayBrightness>();
+        assert_incomplete_on_empty::<Temperature>();
+        assert_incomplete_on_empty::<DeviceName>();
+        assert_incomplete_on_empty::<ActivePreset>();
+        assert_incomplete_on_empty::<PanelState>();
+        assert_incomplete_on_empty::<UdpPort>();
+        assert_incomplete_on_empty::<FanStatus>();
+        assert_incomplete_on_empty::<OverVoltageProtection>();
+        assert_incomplete_on_empty::<OverCurrentProtection>();
+    }
 }
@@ -1,5 +1,7 @@
 //! Error types for the KWR103 power supply crate
 
+use std::io;
+
 use thiserror::Error;
 
 /// Common error type for any kind transactional errors (communication, decoding, etc.)
@@ -7,7 +9,7 @@ use thiserror::Error;
 pub enum TransactionError {
     /// Connection to the power supply failed
     #[error("Ethernet connection error")]
-    EthConnection(#[from] std::io::Error),
+    EthConnection(#[from] io::Error),
 
     /// Connection to the power supply failed
     #[error("Serial connection error")]
@@ -24,6 +26,42 @@ pub enum TransactionError {
     /// Invalid device configuration or parameter
     #[error("Invalid configuration: {0}")]
     InvalidConfiguration(String),
+
+    /// The power supply did not reach the awaited state in time
+    #[error("Timed out waiting for the power supply")]
+    Timeout,
+
+    /// Reading back a setting did not confirm it was applied
+    #[error("Verification failed: {0}")]
+    VerificationFailed(String),
+
+    /// A long-running operation was stopped by raising its cancellation flag
+    #[error("Operation cancelled")]
+    Cancelled,
+
+    /// The serial port or socket is not (or no longer) connected to the power supply
+    #[error("Not connected to the power supply")]
+    NotConnected,
+
+    /// Error while handling a power supply response, along with the raw response
+    #[error("{source} (raw response: \"{}\")", raw.escape_ascii())]
+    RawResponseError {
+        /// The underlying response error
+        source: ResponseError,
+        /// The response bytes as received
+        raw: Vec<u8>,
+    },
+}
+
+impl TransactionError {
+    /// Map I/O errors signalling a missing or lost connection to [`TransactionError::NotConnected`]
+    /// and any other I/O error using `other`
+    pub(crate) fn from_io(err: io::Error, other: impl FnOnce(io::Error) -> Self) -> Self {
+        match err.kind() {
+            io::ErrorKind::NotConnected | io::ErrorKind::BrokenPipe => Self::NotConnected,
+            _ => other(err),
+        }
+    }
 }
 
 /// Errors that may occur while handling a power supply reponse
@@ -37,3 +75,19 @@ pub enum ResponseError {
     #[error("No or incomplete response from power supply")]
     Incomplete,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn disconnect_io_errors_map_to_not_connected() {
+        for kind in [io::ErrorKind::NotConnected, io::ErrorKind::BrokenPipe] {
+            let err = TransactionError::from_io(kind.into(), TransactionError::from);
+            assert!(matches!(err, TransactionError::NotConnected));
+        }
+
+        let err = TransactionError::from_io(io::ErrorKind::Other.into(), TransactionError::from);
+        assert!(matches!(err, TransactionError::EthConnection(_)));
+    }
+}
@@ -1,10 +1,15 @@
 //! UDP communication for ethernet connected power supplies
 
-use std::io;
-use std::net::{Ipv4Addr, ToSocketAddrs, UdpSocket};
+use std::net::{Ipv4Addr, SocketAddr, ToSocketAddrs, UdpSocket};
+use std::sync::mpsc;
 use std::time::Duration;
+use std::{io, thread};
 
-use crate::{Kwr103, ResponseError, TransactionError, Transport};
+use crate::command::DeviceInfo;
+use crate::{
+    count_response_lines, DeviceId, Kwr103, ResponseError, TransactionError, Transport,
+    TransportKind,
+};
 
 /// Communication channel for an ethernet connected power supply
 pub struct EthConnection {
@@ -22,32 +27,91 @@ impl EthConnection {
             read_timeout: Duration::from_millis(150),
         })
     }
+
+    /// Create a new ethernet communication channel, failing if resolving `socket_address` takes
+    /// longer than `timeout`
+    ///
+    /// Resolution happens on a separate thread, which is left to finish on its own on timeout.
+    /// Use [`EthConnection::new`] to rely on the operating system's resolver behavior instead.
+    pub fn with_connect_timeout<A>(
+        socket_address: A,
+        timeout: Duration,
+    ) -> Result<Self, TransactionError>
+    where
+        A: ToSocketAddrs + Send + 'static,
+    {
+        EthConnection::new(resolve_with_timeout(socket_address, timeout)?)
+    }
+}
+
+fn resolve_with_timeout<A>(
+    socket_address: A,
+    timeout: Duration,
+) -> Result<SocketAddr, TransactionError>
+where
+    A: ToSocketAddrs + Send + 'static,
+{
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let _ = tx.send(
+            socket_address
+                .to_socket_addrs()
+                .map(|mut addrs| addrs.next()),
+        );
+    });
+
+    match rx.recv_timeout(timeout) {
+        Ok(Ok(Some(address))) => Ok(address),
+        Ok(Ok(None)) => Err(io::Error::new(io::ErrorKind::NotFound, "No address resolved").into()),
+        Ok(Err(err)) => Err(err.into()),
+        Err(_) => {
+            Err(io::Error::new(io::ErrorKind::TimedOut, "Address resolution timed out").into())
+        }
+    }
 }
 
 impl Transport for EthConnection {
+    fn kind(&self) -> TransportKind {
+        TransportKind::Eth
+    }
+
     fn send(&mut self, bytes: &[u8]) -> Result<(), TransactionError> {
-        if self.socket.send(bytes)? != bytes.len() {
+        let sent = self
+            .socket
+            .send(bytes)
+            .map_err(|err| TransactionError::from_io(err, TransactionError::from))?;
+        if sent != bytes.len() {
             return Err(TransactionError::RequestError);
         }
         Ok(())
     }
 
     fn receive(&mut self) -> Result<Vec<u8>, TransactionError> {
-        receive_udp_with_timeout(&self.socket, self.read_timeout)
+        receive_udp_with_timeout(&self.socket, self.read_timeout, None)
+    }
+
+    fn receive_lines(&mut self, lines: usize) -> Result<Vec<u8>, TransactionError> {
+        receive_udp_with_timeout(&self.socket, self.read_timeout, Some(lines))
+    }
+
+    fn read_timeout(&self) -> Option<Duration> {
+        Some(self.read_timeout)
+    }
+
+    fn set_read_timeout(&mut self, timeout: Duration) -> Result<(), TransactionError> {
+        self.read_timeout = timeout;
+        Ok(())
     }
 }
 
 impl From<EthConnection> for Kwr103 {
     fn from(con: EthConnection) -> Self {
-        Kwr103 {
-            transport: Box::new(con),
-            device_id: None,
-        }
+        Kwr103::new(Box::new(con), DeviceId::None)
     }
 }
 
 /// Connection details for an ethernet connected power supply
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct ConnectionDetails {
     /// IP Address of the power supply
     pub ip: Ipv4Addr,
@@ -63,44 +127,166 @@ impl ConnectionDetails {
     }
 }
 
+impl From<&DeviceInfo> for ConnectionDetails {
+    fn from(info: &DeviceInfo) -> Self {
+        Self {
+            ip: info.ip,
+            port: info.port,
+        }
+    }
+}
+
 const FIND_PATTERN: &[u8] = b"find_ka000";
 
-fn broadcast_find_and_listen() -> Result<Vec<u8>, TransactionError> {
+const DISCOVERY_TIMEOUT: Duration = Duration::from_millis(50);
+
+fn broadcast_find_and_listen() -> Result<Vec<(SocketAddr, Vec<u8>)>, TransactionError> {
     let socket = UdpSocket::bind("0.0.0.0:18191")?;
     socket.set_broadcast(true)?;
     socket.send_to(FIND_PATTERN, "255.255.255.255:18191")?;
 
-    let received = receive_udp_with_timeout(&socket, Duration::from_millis(50))?;
+    receive_datagrams(&socket, DISCOVERY_TIMEOUT)
+}
 
-    if let Some(s) = received.strip_prefix("find_ka000".as_bytes()) {
-        return Ok(s.to_vec());
+/// Options for discovering ethernet connected devices
+#[derive(Debug, Clone)]
+pub struct DiscoveryConfig {
+    /// Connect to the source address of the answer instead of the IP address the device reports
+    ///
+    /// Devices may report a stale IP address, e.g. right after a DHCP change. Enabled by default.
+    pub prefer_source_ip: bool,
+}
+
+impl Default for DiscoveryConfig {
+    fn default() -> Self {
+        Self {
+            prefer_source_ip: true,
+        }
     }
-    Ok(received)
 }
 
-/// Discover serial connected devices
+/// Discover ethernet connected devices
 pub fn find_devices() -> Vec<ConnectionDetails> {
-    if let Ok(received) = broadcast_find_and_listen() {
-        let response = String::from_utf8_lossy(&received);
-        let mut tokens = response.split_whitespace();
-        return tokens
-            .clone()
-            .enumerate()
-            .step_by(3)
-            .filter_map(
-                |(idx, ip_token)| match (ip_token.parse(), tokens.nth(idx + 2)?.parse()) {
-                    (Ok(ip), Ok(port)) => Some(ConnectionDetails { ip, port }),
-                    (_, _) => None,
-                },
-            )
-            .collect();
+    find_devices_with(&DiscoveryConfig::default())
+}
+
+/// Discover ethernet connected devices with non-default options
+///
+/// ```no_run
+/// use kwr103::eth::{self, DiscoveryConfig};
+///
+/// let config = DiscoveryConfig {
+///     prefer_source_ip: false,
+/// };
+/// for details in eth::find_devices_with(&config) {
+///     println!("{}:{}", details.ip, details.port);
+/// }
+/// ```
+pub fn find_devices_with(config: &DiscoveryConfig) -> Vec<ConnectionDetails> {
+    broadcast_find_and_listen()
+        .map(|datagrams| parse_discovery_responses(&datagrams, config))
+        .unwrap_or_default()
+}
+
+/// Query the [`DeviceInfo`] of each of the `details`, e.g. as obtained by [`find_devices`]
+///
+/// Failing to connect to or query a device does not abort, but is reported as its result.
+///
+/// ```no_run
+/// use kwr103::eth;
+///
+/// for (details, info) in eth::describe_devices(&eth::find_devices()) {
+///     match info {
+///         Ok(info) => println!("{}:{}\n{}", details.ip, details.port, info),
+///         Err(err) => eprintln!("{}:{}: {}", details.ip, details.port, err),
+///     }
+/// }
+/// ```
+pub fn describe_devices(
+    details: &[ConnectionDetails],
+) -> Vec<(ConnectionDetails, Result<DeviceInfo, TransactionError>)> {
+    describe_devices_with(details, |details| details.clone().open().map(Kwr103::from))
+}
+
+fn describe_devices_with(
+    details: &[ConnectionDetails],
+    mut open: impl FnMut(&ConnectionDetails) -> Result<Kwr103, TransactionError>,
+) -> Vec<(ConnectionDetails, Result<DeviceInfo, TransactionError>)> {
+    details
+        .iter()
+        .map(|details| {
+            let info = open(details).and_then(|mut kwr103| kwr103.query::<DeviceInfo>());
+            (details.clone(), info)
+        })
+        .collect()
+}
+
+/// Parse the answers to the discovery broadcast, one datagram per responding device
+///
+/// Datagrams not parsing, e.g. the echoed broadcast pattern, and repeated answers from the same
+/// source address are skipped.
+fn parse_discovery_responses(
+    datagrams: &[(SocketAddr, Vec<u8>)],
+    config: &DiscoveryConfig,
+) -> Vec<ConnectionDetails> {
+    let mut sources = Vec::new();
+    datagrams
+        .iter()
+        .filter_map(|(source, datagram)| {
+            if sources.contains(source) {
+                return None;
+            }
+            let mut details = parse_discovery_datagram(datagram)?;
+            sources.push(*source);
+            match source {
+                SocketAddr::V4(source) if config.prefer_source_ip => details.ip = *source.ip(),
+                _ => {}
+            }
+            Some(details)
+        })
+        .collect()
+}
+
+/// Parse a single answer to the discovery broadcast, consisting of whitespace separated
+/// `<IP> <MAC> <PORT>`
+fn parse_discovery_datagram(datagram: &[u8]) -> Option<ConnectionDetails> {
+    let response = String::from_utf8_lossy(datagram);
+    let mut tokens = response.split_whitespace();
+    let ip = tokens.next()?.parse().ok()?;
+    let _mac = tokens.next()?;
+    let port = tokens.next()?.parse().ok()?;
+    Some(ConnectionDetails { ip, port })
+}
+
+/// Receive each datagram arriving at `socket` separately along with its source address, until
+/// no more datagrams arrive within `timeout`
+fn receive_datagrams(
+    socket: &UdpSocket,
+    timeout: Duration,
+) -> Result<Vec<(SocketAddr, Vec<u8>)>, TransactionError> {
+    let mut datagrams = Vec::new();
+    let mut buffer = [0; 4096];
+
+    socket.set_read_timeout(Some(timeout))?;
+
+    loop {
+        match socket.recv_from(&mut buffer) {
+            Ok((size, source)) => datagrams.push((source, buffer[..size].to_vec())),
+            Err(err) => match err.kind() {
+                io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => break,
+                _ => return Err(TransactionError::from_io(err, TransactionError::from)),
+            },
+        }
     }
-    vec![]
+
+    Ok(datagrams)
 }
 
+/// Receive from `socket` until timeout or, if given, until `lines` response lines were received
 fn receive_udp_with_timeout(
     socket: &UdpSocket,
     timeout: Duration,
+    lines: Option<usize>,
 ) -> Result<Vec<u8>, TransactionError> {
     let mut received = Vec::new();
     let mut buffer = [0; 4096];
@@ -111,13 +297,18 @@ fn receive_udp_with_timeout(
         match socket.recv_from(&mut buffer) {
             Ok((size, _)) => {
                 received.extend_from_slice(&buffer[..size]);
+                if lines.is_some_and(|n| count_response_lines(&received) >= n) {
+                    break;
+                }
             }
             Err(err) => match err.kind() {
                 io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => {
                     break;
                 }
                 _ => {
-                    return Err(TransactionError::ResponseError(ResponseError::Incomplete));
+                    return Err(TransactionError::from_io(err, |_| {
+                        ResponseError::Incomplete.into()
+                    }));
                 }
             },
         }
@@ -125,3 +316,197 @@ fn receive_udp_with_timeout(
 
     Ok(received)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockTransport;
+    use std::time::Instant;
+
+    /// Connected pair of a loopback [`EthConnection`] and the socket of a fake power supply
+    fn loopback(read_timeout: Duration) -> (EthConnection, UdpSocket) {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let device = UdpSocket::bind("127.0.0.1:0").unwrap();
+        socket.connect(device.local_addr().unwrap()).unwrap();
+        device.connect(socket.local_addr().unwrap()).unwrap();
+        let con = EthConnection {
+            socket,
+            read_timeout,
+        };
+        (con, device)
+    }
+
+    #[test]
+    fn transport_kind_is_eth() {
+        let (con, _device) = loopback(Duration::from_millis(10));
+        assert_eq!(Kwr103::from(con).transport_kind(), TransportKind::Eth);
+    }
+
+    #[test]
+    fn set_read_timeout_bounds_receive() {
+        let (mut con, _device) = loopback(Duration::from_secs(5));
+        assert_eq!(con.read_timeout(), Some(Duration::from_secs(5)));
+
+        con.set_read_timeout(Duration::from_millis(20)).unwrap();
+        assert_eq!(con.read_timeout(), Some(Duration::from_millis(20)));
+        let start = Instant::now();
+        assert!(con.receive().unwrap().is_empty());
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn receive_lines_returns_once_complete() {
+        let (mut con, device) = loopback(Duration::from_secs(2));
+        device.send(b"1\n12.000\n").unwrap();
+        device.send(b"0.500\n").unwrap();
+
+        let start = Instant::now();
+        assert_eq!(con.receive_lines(3).unwrap(), b"1\n12.000\n0.500\n");
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn receive_without_hint_waits_for_timeout() {
+        let (mut con, device) = loopback(Duration::from_millis(50));
+        device.send(b"12.000\n").unwrap();
+
+        let start = Instant::now();
+        assert_eq!(con.receive().unwrap(), b"12.000\n");
+        assert!(start.elapsed() >= Duration::from_millis(50));
+    }
+
+    #[test]
+    fn resolve_address_with_timeout() {
+        let timeout = Duration::from_millis(500);
+
+        let resolved = resolve_with_timeout(("127.0.0.1", 18190), timeout).unwrap();
+        assert_eq!(resolved, SocketAddr::from(([127, 0, 0, 1], 18190)));
+
+        let unresolvable = resolve_with_timeout("kwr103.invalid:18190", timeout);
+        assert!(unresolvable.is_err_and(|e| matches!(e, TransactionError::EthConnection(_))));
+    }
+
+    #[test]
+    fn parse_discovery_responses_of_multiple_devices() {
+        let datagrams = [
+            ("192.168.1.2:18191", &b"find_ka000"[..]),
+            (
+                "192.168.1.198:18191",
+                b"192.168.1.198 88-06-00-00-ff-ff 18190\n",
+            ),
+            (
+                "192.168.1.199:18191",
+                b"192.168.1.199 88-06-00-00-ff-fe 18191\n",
+            ),
+            (
+                "192.168.1.199:18191",
+                b"192.168.1.199 88-06-00-00-ff-fe 18191\n",
+            ),
+        ]
+        .map(|(source, datagram)| (source.parse().unwrap(), datagram.to_vec()));
+        let devices = parse_discovery_responses(&datagrams, &DiscoveryConfig::default());
+        assert_eq!(devices.len(), 2);
+        assert_eq!(devices[0].ip, Ipv4Addr::new(192, 168, 1, 198));
+        assert_eq!(devices[0].port, 18190);
+        assert_eq!(devices[1].ip, Ipv4Addr::new(192, 168, 1, 199));
+        assert_eq!(devices[1].port, 18191);
+    }
+
+    #[test]
+    fn describe_devices_continues_past_failures() {
+        let details = [
+            ConnectionDetails {
+                ip: Ipv4Addr::new(192, 168, 1, 198),
+                port: 18190,
+            },
+            ConnectionDetails {
+                ip: Ipv4Addr::new(192, 168, 1, 199),
+                port: 18190,
+            },
+        ];
+        let described = describe_devices_with(&details, |details| {
+            let mock = MockTransport::default();
+            if details.ip == Ipv4Addr::new(192, 168, 1, 198) {
+                mock.respond(b"DHCP:0\nIP:192.168.1.198\nNETMASK:255.255.255.0\nGateWay:192.168.1.1\nMAC:88-06-00-00-ff-ff\nPORT:18190\nBAUDRATE:115200\n");
+            } else {
+                mock.respond(b"garbage\n");
+            }
+            Ok(Kwr103::from(mock))
+        });
+
+        assert_eq!(described.len(), 2);
+        assert_eq!(described[0].0.ip, Ipv4Addr::new(192, 168, 1, 198));
+        assert_eq!(
+            described[0].1.as_ref().unwrap().mac.to_string(),
+            "88:06:00:00:ff:ff"
+        );
+        assert_eq!(described[1].0.ip, Ipv4Addr::new(192, 168, 1, 199));
+        assert!(described[1].1.is_err());
+    }
+
+    #[test]
+    fn parse_discovery_responses_prefers_source_ip() {
+        let datagrams = [(
+            "192.168.1.42:18191".parse().unwrap(),
+            b"192.168.1.198 88-06-00-00-ff-ff 18190\n".to_vec(),
+        )];
+
+        let devices = parse_discovery_responses(&datagrams, &DiscoveryConfig::default());
+        assert_eq!(devices[0].ip, Ipv4Addr::new(192, 168, 1, 42));
+        assert_eq!(devices[0].port, 18190);
+
+        let config = DiscoveryConfig {
+            prefer_source_ip: false,
+        };
+        let devices = parse_discovery_responses(&datagrams, &config);
+        assert_eq!(devices[0].ip, Ipv4Addr::new(192, 168, 1, 198));
+        assert_eq!(devices[0].port, 18190);
+    }
+
+    #[test]
+    fn connection_details_dedup_in_set() {
+        let details = |ip, port| ConnectionDetails { ip, port };
+        let set: std::collections::HashSet<_> = [
+            details(Ipv4Addr::new(192, 168, 1, 198), 18190),
+            details(Ipv4Addr::new(192, 168, 1, 198), 18190),
+            details(Ipv4Addr::new(192, 168, 1, 198), 18191),
+            details(Ipv4Addr::new(192, 168, 1, 199), 18190),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(set.len(), 3);
+    }
+
+    #[test]
+    fn parse_discovery_datagram_skips_garbage() {
+        assert!(parse_discovery_datagram(b"").is_none());
+        assert!(parse_discovery_datagram(b"garbage 88-06-00-00-ff-ff 18190\n").is_none());
+        assert!(parse_discovery_datagram(b"192.168.1.198 88-06-00-00-ff-ff\n").is_none());
+    }
+
+    #[test]
+    fn receive_datagrams_separately() {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let first = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let second = UdpSocket::bind("127.0.0.1:0").unwrap();
+        first
+            .send_to(
+                b"192.168.1.198 88-06-00-00-ff-ff 18190\n",
+                socket.local_addr().unwrap(),
+            )
+            .unwrap();
+        second
+            .send_to(
+                b"192.168.1.199 88-06-00-00-ff-fe 18190\n",
+                socket.local_addr().unwrap(),
+            )
+            .unwrap();
+
+        let datagrams = receive_datagrams(&socket, Duration::from_millis(50)).unwrap();
+        assert_eq!(datagrams.len(), 2);
+        assert_eq!(datagrams[0].0, first.local_addr().unwrap());
+        assert_eq!(datagrams[1].0, second.local_addr().unwrap());
+        let config = DiscoveryConfig::default();
+        assert_eq!(parse_discovery_responses(&datagrams, &config).len(), 2);
+    }
+}
@@ -5,11 +5,12 @@
 //!
 //! # Example
 //! ```no_run
-//! use kwr103::{command::*, Kwr103, TransactionError, UsbConnection};
+//! use kwr103::{command::*, BaudRate, Kwr103, TransactionError, UsbConnection};
 //!
 //! fn main() -> Result<(), TransactionError> {
 //!     // Establish USB connection
-//!     let mut kwr103: Kwr103 = UsbConnection::new("/dev/ttyACM0", 115200, None)?.into();
+//!     let usb = UsbConnection::new("/dev/ttyACM0", BaudRate::B115200, None)?;
+//!     let mut kwr103: Kwr103 = usb.into();
 //!
 //!     // Adjust voltage and current settings
 //!     kwr103.command(Voltage(42.0))?;
@@ -57,15 +58,64 @@
 pub mod command;
 pub mod error;
 pub mod eth;
+pub mod mock;
+pub mod record;
 pub mod usb;
 
 pub use error::{ResponseError, TransactionError};
 pub use eth::EthConnection;
-pub use usb::UsbConnection;
+pub use mock::MockTransport;
+pub use record::RecordingTransport;
+pub use usb::{BaudRate, UsbConnection, UsbConnectionBuilder};
 
 #[doc(hidden)]
 pub mod cli;
 
+use std::collections::VecDeque;
+use std::fmt;
+use std::ops::RangeInclusive;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Addressing of the power supply within the `[ID]` field of commands and queries
+///
+/// Power supplies on an RS485 bus are addressed by their device id, while a single directly
+/// connected power supply does not require one.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum DeviceId {
+    /// No device id, the `[ID]` field is ommitted
+    #[default]
+    None,
+    /// Address all power supplies on the RS485 bus (serialized as id `00`)
+    ///
+    /// Only sensible for commands, see [`Kwr103::broadcast`]. A query addressed to id `00` makes
+    /// every unit on the bus answer at once, garbling the responses.
+    Broadcast,
+    /// Address the power supply with the given id in [1; 99]
+    Id(u8),
+}
+
+impl From<Option<u8>> for DeviceId {
+    fn from(device_id: Option<u8>) -> Self {
+        match device_id {
+            Some(id) => DeviceId::Id(id),
+            None => DeviceId::None,
+        }
+    }
+}
+
+impl fmt::Display for DeviceId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DeviceId::None => Ok(()),
+            DeviceId::Broadcast => write!(f, "00"),
+            DeviceId::Id(id) => write!(f, "{:02}", id),
+        }
+    }
+}
+
 /// A command to be issued to the power supply.
 ///
 /// Types implementing this trait represent commands that are intended to change settings or the
@@ -81,14 +131,35 @@ pub mod cli;
 /// serialized payload should look like
 /// `VSET01:12.0\n`.
 ///
-/// If `device_id` is `None`, the `[ID]` field is ommitted.
+/// If `device_id` is [`DeviceId::None`], the `[ID]` field is ommitted.
 ///
 /// Additionally, commands may even be concatenated (separated by the newline character), e.g.
 /// `VSET01:42.0\nISET01:2.3\n` both sets the output voltage to 42.0V as well as the output current
 /// to 2.3A
 pub trait Command: Sized {
     /// Serialize the command to bytes for sending on the serial interface
-    fn serialize(cmd: Self, device_id: Option<u8>) -> Vec<u8>;
+    fn serialize(cmd: Self, device_id: DeviceId) -> Vec<u8>;
+
+    /// Serialize the command without its trailing newline, e.g. to join several commands with a
+    /// custom delimiter
+    fn serialize_body(cmd: Self, device_id: DeviceId) -> Vec<u8> {
+        strip_newline(Self::serialize(cmd, device_id))
+    }
+
+    /// Serialize the command to a human-readable string without trailing newline, e.g. for
+    /// logging
+    fn serialize_str(cmd: Self, device_id: DeviceId) -> String {
+        String::from_utf8_lossy(&Self::serialize(cmd, device_id))
+            .trim_end()
+            .to_string()
+    }
+
+    /// Check that the command can be sent, before it is serialized
+    ///
+    /// Defaults to accepting any value.
+    fn validate(&self) -> Result<(), TransactionError> {
+        Ok(())
+    }
 }
 
 /// A query to be issued to the power supply.
@@ -107,25 +178,264 @@ pub trait Command: Sized {
 /// so for example a query for the output voltage setting on the power supply with id 1 serializes
 /// as `VSET01?\n`, with the response following as `42.0\n` (newline terminated value).
 ///
-/// If `device_id` is `None`, the `[ID]` field is ommitted.
+/// If `device_id` is [`DeviceId::None`], the `[ID]` field is ommitted.
 ///
 /// Additionally, queries may even be concatenated (separated by the newline character),
 /// e.g. `VSET01?\nISET01?\n` queries both the output voltage and current.
 pub trait Query: Sized {
+    /// Number of newline-terminated lines the power supply answers with
+    ///
+    /// Transports use this hint to return as soon as the response is complete instead of waiting
+    /// for the read timeout.
+    const RESPONSE_LINES: usize = 1;
+
     /// Serialize to bytes for sending
-    fn serialize(device_id: Option<u8>) -> Vec<u8>;
+    fn serialize(device_id: DeviceId) -> Vec<u8>;
+
+    /// Serialize without the trailing newline, e.g. to join several queries with a custom
+    /// delimiter
+    fn serialize_body(device_id: DeviceId) -> Vec<u8> {
+        strip_newline(Self::serialize(device_id))
+    }
+
+    /// Serialize to a human-readable string without trailing newline, e.g. for logging
+    fn serialize_str(device_id: DeviceId) -> String {
+        String::from_utf8_lossy(&Self::serialize(device_id))
+            .trim_end()
+            .to_string()
+    }
 
     /// Parse `bytes` response from the power supply
     fn parse(bytes: &[u8]) -> std::result::Result<Self, ResponseError>;
 }
 
+/// An analog setting, both settable and queryable using the same mnemonic
+///
+/// Implementing `Setting` provides the [`Command`] and [`Query`] implementations, serializing as
+/// `<MNEMONIC>[ID]:<VAL>\n` with three decimals resp. `<MNEMONIC>[ID]?\n`.
+///
+/// ```
+/// use kwr103::{Command, DeviceId, Setting};
+///
+/// struct OverVoltageDelay(f32);
+///
+/// impl Setting for OverVoltageDelay {
+///     type Value = f32;
+///     const MNEMONIC: &'static str = "OVPDLY";
+///
+///     fn from_value(value: f32) -> Self {
+///         Self(value)
+///     }
+///
+///     fn value(&self) -> &f32 {
+///         &self.0
+///     }
+/// }
+///
+/// assert_eq!(
+///     Command::serialize(OverVoltageDelay(0.5), DeviceId::Id(1)),
+///     b"OVPDLY01:0.500\n"
+/// );
+/// ```
+pub trait Setting: Sized {
+    /// Type of the setting's value
+    type Value: fmt::Display + std::str::FromStr;
+
+    /// Mnemonic shared by command and query, e.g. `VSET`
+    const MNEMONIC: &'static str;
+
+    /// Wrap a parsed `value`
+    fn from_value(value: Self::Value) -> Self;
+
+    /// The value to serialize
+    fn value(&self) -> &Self::Value;
+}
+
+/// A [`Command`] whose effect can be read back from the power supply by a [`Query`]
+///
+/// This allows to build generic verification loops, e.g. re-issuing a command until the readback
+/// matches:
+/// ```no_run
+/// use kwr103::{command::*, BaudRate, Kwr103, UsbConnection, Verifiable};
+///
+/// let usb = UsbConnection::new("/dev/ttyACM0", BaudRate::B115200, None).unwrap();
+/// let mut kwr103 = Kwr103::from(usb);
+/// let setting = Voltage(12.0);
+/// kwr103.command(setting.clone()).unwrap();
+/// let readback = kwr103.query::<<Voltage as Verifiable>::Readback>().unwrap();
+/// assert!(setting.matches(&readback));
+/// ```
+pub trait Verifiable: Command {
+    /// The query reading back the setting changed by the command
+    type Readback: Query;
+
+    /// Whether the `readback` reflects this command having been applied
+    fn matches(&self, readback: &Self::Readback) -> bool;
+}
+
+/// Physical connection type of a [`Transport`]
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum TransportKind {
+    /// Serial connection via USB or RS485, see [`UsbConnection`]
+    Usb,
+    /// UDP connection via ethernet, see [`EthConnection`]
+    Eth,
+}
+
 /// A type implementing `Transport` defines how to physically communicate with the power supply
 pub trait Transport {
+    /// The physical connection type
+    fn kind(&self) -> TransportKind;
+
     /// Attempt to send `bytes` to the power supply
     fn send(&mut self, bytes: &[u8]) -> Result<(), TransactionError>;
 
     /// Receive bytes from the power supply
     fn receive(&mut self) -> Result<Vec<u8>, TransactionError>;
+
+    /// Receive bytes from the power supply, returning early once `lines` response lines arrived
+    ///
+    /// Defaults to [`Transport::receive`], i.e. waiting for the read timeout.
+    fn receive_lines(&mut self, _lines: usize) -> Result<Vec<u8>, TransactionError> {
+        self.receive()
+    }
+
+    /// The time to wait for a response, if the transport has a configurable read timeout
+    ///
+    /// Defaults to `None`.
+    fn read_timeout(&self) -> Option<Duration> {
+        None
+    }
+
+    /// Change the time to wait for a response
+    ///
+    /// Defaults to failing with [`TransactionError::InvalidConfiguration`] for transports without
+    /// a configurable read timeout.
+    fn set_read_timeout(&mut self, _timeout: Duration) -> Result<(), TransactionError> {
+        Err(TransactionError::InvalidConfiguration(
+            "Transport does not support read timeouts".to_string(),
+        ))
+    }
+
+    /// Perform transport specific teardown before the connection is dropped
+    ///
+    /// Defaults to doing nothing.
+    fn close(&mut self) -> Result<(), TransactionError> {
+        Ok(())
+    }
+}
+
+/// Remove a single trailing newline from a serialized payload
+/// Forward to the boxed transport, e.g. to wrap the transport of a [`Kwr103`] in a
+/// [`record::RecordingTransport`]
+impl<T: Transport + ?Sized> Transport for Box<T> {
+    fn kind(&self) -> TransportKind {
+        (**self).kind()
+    }
+
+    fn send(&mut self, bytes: &[u8]) -> Result<(), TransactionError> {
+        (**self).send(bytes)
+    }
+
+    fn receive(&mut self) -> Result<Vec<u8>, TransactionError> {
+        (**self).receive()
+    }
+
+    fn receive_lines(&mut self, lines: usize) -> Result<Vec<u8>, TransactionError> {
+        (**self).receive_lines(lines)
+    }
+
+    fn read_timeout(&self) -> Option<Duration> {
+        (**self).read_timeout()
+    }
+
+    fn set_read_timeout(&mut self, timeout: Duration) -> Result<(), TransactionError> {
+        (**self).set_read_timeout(timeout)
+    }
+
+    fn close(&mut self) -> Result<(), TransactionError> {
+        (**self).close()
+    }
+}
+
+fn strip_newline(mut payload: Vec<u8>) -> Vec<u8> {
+    if payload.ends_with(b"\n") {
+        payload.pop();
+    }
+    payload
+}
+
+/// Count the complete response lines in `bytes`, ignoring echoed queries (ending with `?`)
+pub(crate) fn count_response_lines(bytes: &[u8]) -> usize {
+    bytes
+        .split_inclusive(|&b| b == b'\n')
+        .filter(|line| line.ends_with(b"\n") && !line.ends_with(b"?\n"))
+        .count()
+}
+
+/// Power conversion efficiency from `input_w` and `output_w` power in watts, clamped to [0; 1]
+///
+/// Nonsensical inputs, e.g. a non-positive input power, yield an efficiency of zero.
+///
+/// # Example
+/// ```no_run
+/// use kwr103::{efficiency, BaudRate, Kwr103, UsbConnection};
+///
+/// let usb = UsbConnection::new("/dev/ttyACM0", BaudRate::B115200, None).unwrap();
+/// let mut input = Kwr103::from(usb);
+/// let usb = UsbConnection::new("/dev/ttyACM1", BaudRate::B115200, None).unwrap();
+/// let mut output = Kwr103::from(usb);
+///
+/// let eta = efficiency(input.power_reading().unwrap(), output.power_reading().unwrap());
+/// println!("Efficiency: {:.1}%", eta * 100.0);
+/// ```
+pub fn efficiency(input_w: f32, output_w: f32) -> f32 {
+    let eta = output_w / input_w;
+    if input_w <= 0.0 || !eta.is_finite() {
+        return 0.0;
+    }
+    eta.clamp(0.0, 1.0)
+}
+
+/// Setpoints of a linear ramp from `from` to `to` in `steps` equal steps
+///
+/// The starting value is not included, so the last of the `steps` values is `to`. Zero `steps`
+/// yield no values.
+///
+/// # Example
+/// ```no_run
+/// use std::{thread, time::Duration};
+/// use kwr103::{command::*, ramp_duration, ramp_steps, BaudRate, Kwr103, UsbConnection};
+///
+/// let usb = UsbConnection::new("/dev/ttyACM0", BaudRate::B115200, None).unwrap();
+/// let mut kwr103 = Kwr103::from(usb);
+/// let delay = Duration::from_millis(200);
+/// let plan = ramp_steps(0.0, 12.0, 24);
+/// println!("Ramping to 12 V takes {:?}", ramp_duration(plan.len(), delay));
+/// for voltage in plan {
+///     kwr103.command(Voltage(voltage)).unwrap();
+///     thread::sleep(delay);
+/// }
+/// ```
+pub fn ramp_steps(from: f32, to: f32, steps: usize) -> Vec<f32> {
+    (1..=steps)
+        .map(|step| from + (to - from) * step as f32 / steps as f32)
+        .collect()
+}
+
+/// Time a ramp of `steps` steps takes, waiting `step_delay` after setting each step
+pub fn ramp_duration(steps: usize, step_delay: Duration) -> Duration {
+    step_delay.saturating_mul(u32::try_from(steps).unwrap_or(u32::MAX))
+}
+
+/// Check that `id` is a valid KWR103 RS485 device id
+pub(crate) fn validate_device_id(id: u8) -> Result<(), TransactionError> {
+    if id == 0 || id > 99 {
+        return Err(TransactionError::InvalidConfiguration(
+            "KWR103 RS485 device id must be in [1; 99]".to_string(),
+        ));
+    }
+    Ok(())
 }
 
 /// A KWR103 type power supply
@@ -137,16 +447,216 @@ pub trait Transport {
 /// connection details.
 ///
 /// ```no_run
-/// use kwr103::{Kwr103, UsbConnection};
+/// use kwr103::{BaudRate, Kwr103, UsbConnection};
 ///
-/// let mut kwr103 = Kwr103::from(UsbConnection::new("/dev/ttyACM0", 115200, None).unwrap());
+/// let usb = UsbConnection::new("/dev/ttyACM0", BaudRate::B115200, None).unwrap();
+/// let mut kwr103 = Kwr103::from(usb);
 /// ```
 pub struct Kwr103 {
     transport: Box<dyn Transport>,
-    device_id: Option<u8>,
+    device_id: DeviceId,
+    transaction_log: Option<TransactionLog>,
+    ratings: Option<command::Ratings>,
+    max_payload: Option<usize>,
+    cancel: Option<Arc<AtomicBool>>,
+}
+
+/// Interval at which the cancellation flag is checked while waiting, see
+/// [`Kwr103::with_cancellation`]
+const CANCEL_CHECK_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Delay between consecutive transmissions of a batch split by [`Kwr103::with_max_payload`]
+const INTER_CHUNK_DELAY: Duration = Duration::from_millis(10);
+
+/// A sequence of commands sent together in as few transmissions as possible
+///
+/// Created by [`Kwr103::batch`].
+pub struct Batch<'a> {
+    kwr103: &'a mut Kwr103,
+    commands: Vec<Vec<u8>>,
+    error: Option<TransactionError>,
+}
+
+impl Batch<'_> {
+    /// Append a [`Command`] to the batch
+    pub fn command<C: Command>(mut self, cmd: C) -> Self {
+        match cmd.validate() {
+            Ok(()) => self.commands.push(C::serialize(cmd, self.kwr103.device_id)),
+            Err(err) => {
+                self.error.get_or_insert(err);
+            }
+        }
+        self
+    }
+
+    /// Send all commands of the batch
+    ///
+    /// Nothing is sent if any of the commands failed validation.
+    pub fn send(self) -> Result<(), TransactionError> {
+        if let Some(err) = self.error {
+            return Err(err);
+        }
+        self.kwr103.send_commands(&self.commands)
+    }
+}
+
+/// Upper bound for the number of transactions retained by [`Kwr103::with_transaction_log`]
+pub const MAX_TRANSACTION_LOG: usize = 1024;
+
+/// Bounded history of recent request/response pairs
+struct TransactionLog {
+    capacity: usize,
+    entries: VecDeque<(Vec<u8>, Vec<u8>)>,
+}
+
+impl TransactionLog {
+    fn record(&mut self, request: &[u8], response: &[u8]) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries
+            .push_back((request.to_vec(), response.to_vec()));
+    }
 }
 
 impl Kwr103 {
+    pub(crate) fn new(transport: Box<dyn Transport>, device_id: DeviceId) -> Self {
+        Kwr103 {
+            transport,
+            device_id,
+            transaction_log: None,
+            ratings: None,
+            max_payload: None,
+            cancel: None,
+        }
+    }
+
+    /// Configure the rated limits to validate setpoints against, see [`command::Ratings`].
+    ///
+    /// # Example
+    /// ```no_run
+    /// use kwr103::{command::*, BaudRate, Kwr103, UsbConnection};
+    ///
+    /// let usb = UsbConnection::new("/dev/ttyACM0", BaudRate::B115200, None).unwrap();
+    /// let mut kwr103 = Kwr103::from(usb);
+    /// let ratings = kwr103.query::<Ratings>().unwrap();
+    /// let mut kwr103 = kwr103.with_ratings(ratings);
+    /// ```
+    pub fn with_ratings(mut self, ratings: command::Ratings) -> Self {
+        self.ratings = Some(ratings);
+        self
+    }
+
+    /// Stop long-running loops once `flag` is raised, e.g. from another thread.
+    ///
+    /// [`Kwr103::status_stream`], [`Kwr103::run_current_watchdog`], [`Kwr103::measure_until`],
+    /// [`Kwr103::apply_sequence`] and [`Kwr103::pulse_output`] check the flag on each iteration
+    /// and while waiting, and return [`TransactionError::Cancelled`] once it is set.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use std::sync::{atomic::AtomicBool, Arc};
+    /// use kwr103::{BaudRate, Kwr103, UsbConnection};
+    ///
+    /// let stop = Arc::new(AtomicBool::new(false));
+    /// let usb = UsbConnection::new("/dev/ttyACM0", BaudRate::B115200, None).unwrap();
+    /// let mut kwr103 = Kwr103::from(usb).with_cancellation(Arc::clone(&stop));
+    /// ```
+    pub fn with_cancellation(mut self, flag: Arc<AtomicBool>) -> Self {
+        self.cancel = Some(flag);
+        self
+    }
+
+    fn check_cancelled(&self) -> Result<(), TransactionError> {
+        match &self.cancel {
+            Some(flag) if flag.load(Ordering::Relaxed) => Err(TransactionError::Cancelled),
+            _ => Ok(()),
+        }
+    }
+
+    /// Sleep for `duration`, returning early with [`TransactionError::Cancelled`] once cancelled
+    fn sleep_cancellable(&self, duration: Duration) -> Result<(), TransactionError> {
+        if self.cancel.is_none() {
+            thread::sleep(duration);
+            return Ok(());
+        }
+        let deadline = Instant::now() + duration;
+        loop {
+            self.check_cancelled()?;
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Ok(());
+            }
+            thread::sleep(remaining.min(CANCEL_CHECK_INTERVAL));
+        }
+    }
+
+    /// The physical connection type of the underlying [`Transport`].
+    pub fn transport_kind(&self) -> TransportKind {
+        self.transport.kind()
+    }
+
+    /// Consume the power supply handle and return the underlying [`Transport`].
+    ///
+    /// Use this to reconfigure or explicitly close the connection.
+    pub fn into_transport(self) -> Box<dyn Transport> {
+        self.transport
+    }
+
+    /// Close the connection to the power supply, reporting errors on teardown.
+    ///
+    /// Dropping the handle closes the connection as well, but silently ignores such errors.
+    pub fn close(mut self) -> Result<(), TransactionError> {
+        self.transport.close()
+    }
+
+    /// Log every payload sent and received to `sink`, see [`record::RecordingTransport`].
+    ///
+    /// # Example
+    /// ```no_run
+    /// use kwr103::{BaudRate, Kwr103, UsbConnection};
+    ///
+    /// let usb = UsbConnection::new("/dev/ttyACM0", BaudRate::B115200, None).unwrap();
+    /// let mut kwr103 = Kwr103::from(usb).with_logging_transport(std::io::stderr());
+    /// ```
+    pub fn with_logging_transport<W: std::io::Write + 'static>(mut self, sink: W) -> Self {
+        self.transport = Box::new(record::RecordingTransport::new(self.transport, sink));
+        self
+    }
+
+    /// Retain the last `capacity` request/response pairs in memory.
+    ///
+    /// The `capacity` is capped at [`MAX_TRANSACTION_LOG`]. Commands are recorded with an empty
+    /// response. Use [`Kwr103::recent_transactions`] to read back the log, e.g. after an error.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use kwr103::{BaudRate, Kwr103, UsbConnection};
+    ///
+    /// let usb = UsbConnection::new("/dev/ttyACM0", BaudRate::B115200, None).unwrap();
+    /// let mut kwr103 = Kwr103::from(usb).with_transaction_log(16);
+    /// ```
+    pub fn with_transaction_log(mut self, capacity: usize) -> Self {
+        let capacity = capacity.min(MAX_TRANSACTION_LOG);
+        self.transaction_log = Some(TransactionLog {
+            capacity,
+            entries: VecDeque::with_capacity(capacity),
+        });
+        self
+    }
+
+    /// Recently issued request/response pairs, oldest first.
+    ///
+    /// Yields nothing unless enabled by [`Kwr103::with_transaction_log`].
+    pub fn recent_transactions(&self) -> impl Iterator<Item = &(Vec<u8>, Vec<u8>)> {
+        self.transaction_log
+            .iter()
+            .flat_map(|log| log.entries.iter())
+    }
+
     /// Issue a [`Command`] to the power supply.
     ///
     /// Commands do not trigger any response from the power supply, so there is no acknowledgement
@@ -155,14 +665,244 @@ impl Kwr103 {
     ///
     /// # Example
     /// ```no_run
-    /// use kwr103::{command::*, Kwr103, UsbConnection};
+    /// use kwr103::{command::*, BaudRate, Kwr103, UsbConnection};
     ///
-    /// let mut kwr103 = Kwr103::from(UsbConnection::new("/dev/ttyACM0", 115200, None).unwrap());
+    /// let usb = UsbConnection::new("/dev/ttyACM0", BaudRate::B115200, None).unwrap();
+    /// let mut kwr103 = Kwr103::from(usb);
     /// kwr103.command(Voltage(42.0)).unwrap();
     /// ```
     pub fn command<C: Command>(&mut self, cmd: C) -> Result<(), TransactionError> {
+        cmd.validate()?;
         let payload = C::serialize(cmd, self.device_id);
-        self.transport.send(payload.as_slice())
+        self.send_payload(&payload)
+    }
+
+    /// Issue a [`Command`] by reference, e.g. to repeatedly send the same command.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use kwr103::{command::*, BaudRate, Kwr103, UsbConnection};
+    ///
+    /// let usb = UsbConnection::new("/dev/ttyACM0", BaudRate::B115200, None).unwrap();
+    /// let mut kwr103 = Kwr103::from(usb);
+    /// let off = Output(Switch::Off);
+    /// for _ in 0..3 {
+    ///     kwr103.command_ref(&off).unwrap();
+    /// }
+    /// ```
+    pub fn command_ref<C: Command + Clone>(&mut self, cmd: &C) -> Result<(), TransactionError> {
+        self.command(cmd.clone())
+    }
+
+    /// Issue a [`Command`] to all power supplies on the RS485 bus at once.
+    ///
+    /// The command is addressed to the broadcast id `00` regardless of the configured device id.
+    /// There is no broadcast counterpart of [`Kwr103::query`], as all units would answer at once.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use kwr103::{command::*, BaudRate, Kwr103, UsbConnection};
+    ///
+    /// let usb = UsbConnection::new("/dev/ttyUSB0", BaudRate::B115200, Some(1)).unwrap();
+    /// let mut kwr103 = Kwr103::from(usb);
+    /// kwr103.broadcast(Output(Switch::Off)).unwrap();
+    /// ```
+    pub fn broadcast<C: Command>(&mut self, cmd: C) -> Result<(), TransactionError> {
+        cmd.validate()?;
+        let payload = C::serialize(cmd, DeviceId::Broadcast);
+        self.send_payload(&payload)
+    }
+
+    /// Set output voltage and current and switch the output on, all in a single transmission.
+    ///
+    /// If ratings are configured using [`Kwr103::with_ratings`], `v` and `i` are validated against
+    /// them before sending anything.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use kwr103::{BaudRate, Kwr103, UsbConnection};
+    ///
+    /// let usb = UsbConnection::new("/dev/ttyACM0", BaudRate::B115200, None).unwrap();
+    /// let mut kwr103 = Kwr103::from(usb);
+    /// kwr103.set_and_enable(12.0, 0.5).unwrap();
+    /// ```
+    pub fn set_and_enable(&mut self, v: f32, i: f32) -> Result<(), TransactionError> {
+        if let Some(ratings) = self.ratings {
+            check_limit("Voltage", v, ratings.max_voltage)?;
+            check_limit("Current", i, ratings.max_current)?;
+        }
+
+        self.batch()
+            .command(command::Voltage(v))
+            .command(command::Current(i))
+            .command(command::Output(command::Switch::On))
+            .send()
+    }
+
+    /// Set output voltage and current in a single transmission and read back both settings.
+    ///
+    /// Fails with [`TransactionError::VerificationFailed`] unless the readback matches `v` and `i`
+    /// within the resolution of the power supply, see [`Verifiable`]. The output state is left
+    /// unchanged.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use kwr103::{BaudRate, Kwr103, UsbConnection};
+    ///
+    /// let usb = UsbConnection::new("/dev/ttyACM0", BaudRate::B115200, None).unwrap();
+    /// let mut kwr103 = Kwr103::from(usb);
+    /// let setpoints = kwr103.configure(12.0, 0.5).unwrap();
+    /// println!("{} V, {} A", setpoints.voltage, setpoints.current);
+    /// ```
+    pub fn configure(&mut self, v: f32, i: f32) -> Result<command::Setpoints, TransactionError> {
+        if let Some(ratings) = self.ratings {
+            check_limit("Voltage", v, ratings.max_voltage)?;
+            check_limit("Current", i, ratings.max_current)?;
+        }
+
+        let (voltage, current) = (command::Voltage(v), command::Current(i));
+        self.batch()
+            .command(voltage.clone())
+            .command(current.clone())
+            .send()?;
+
+        let voltage_readback = self.query::<command::Voltage>()?;
+        if !voltage.matches(&voltage_readback) {
+            return Err(TransactionError::VerificationFailed(format!(
+                "Voltage set to {} V, but read back {} V",
+                v, voltage_readback.0
+            )));
+        }
+        let current_readback = self.query::<command::Current>()?;
+        if !current.matches(&current_readback) {
+            return Err(TransactionError::VerificationFailed(format!(
+                "Current set to {} A, but read back {} A",
+                i, current_readback.0
+            )));
+        }
+
+        Ok(command::Setpoints {
+            voltage: voltage_readback.0,
+            current: current_readback.0,
+        })
+    }
+
+    /// Start a [`Batch`] of commands to be sent together.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use kwr103::{command::*, BaudRate, Kwr103, UsbConnection};
+    ///
+    /// let usb = UsbConnection::new("/dev/ttyACM0", BaudRate::B115200, None).unwrap();
+    /// let mut kwr103 = Kwr103::from(usb);
+    /// kwr103
+    ///     .batch()
+    ///     .command(Voltage(12.0))
+    ///     .command(Current(0.5))
+    ///     .send()
+    ///     .unwrap();
+    /// ```
+    pub fn batch(&mut self) -> Batch<'_> {
+        Batch {
+            kwr103: self,
+            commands: Vec::new(),
+            error: None,
+        }
+    }
+
+    /// Limit the number of bytes sent at once when sending several commands together.
+    ///
+    /// Batches exceeding `max_payload` are split into multiple transmissions, separated by a
+    /// short delay, to avoid overrunning the input buffer of the power supply.
+    pub fn with_max_payload(mut self, max_payload: usize) -> Self {
+        self.max_payload = Some(max_payload);
+        self
+    }
+
+    /// Issue the same [`Command`] to several power supplies sharing an RS485 bus.
+    ///
+    /// The command is serialized once per device id and all payloads are sent together. As
+    /// commands trigger no response, there is no per-device result.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use kwr103::{command::*, BaudRate, Kwr103, UsbConnection};
+    ///
+    /// let usb = UsbConnection::new("/dev/ttyUSB0", BaudRate::B115200, Some(1)).unwrap();
+    /// let mut kwr103 = Kwr103::from(usb);
+    /// kwr103.command_all(&[1, 2, 3], Voltage(12.0)).unwrap();
+    /// ```
+    pub fn command_all<C: Command + Clone>(
+        &mut self,
+        ids: &[u8],
+        cmd: C,
+    ) -> Result<(), TransactionError> {
+        cmd.validate()?;
+        let mut commands = Vec::new();
+        for &id in ids {
+            validate_device_id(id)?;
+            commands.push(C::serialize(cmd.clone(), DeviceId::Id(id)));
+        }
+        self.send_commands(&commands)
+    }
+
+    /// Probe each RS485 device id in `ids` and return those a power supply responds to.
+    ///
+    /// Each id is probed by querying its voltage setting, ids yielding no or an invalid response
+    /// are considered unused. Errors of the underlying transport abort the scan.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use kwr103::{BaudRate, Kwr103, UsbConnection};
+    ///
+    /// let usb = UsbConnection::new("/dev/ttyUSB0", BaudRate::B115200, None).unwrap();
+    /// let mut kwr103 = Kwr103::from(usb);
+    /// for id in kwr103.scan_device_ids(1..=10).unwrap() {
+    ///     println!("Device id {} responds", id);
+    /// }
+    /// ```
+    pub fn scan_device_ids(
+        &mut self,
+        ids: RangeInclusive<u8>,
+    ) -> Result<Vec<u8>, TransactionError> {
+        let device_id = self.device_id;
+        let mut found = Vec::new();
+        for id in ids {
+            validate_device_id(id)?;
+            self.device_id = DeviceId::Id(id);
+            let result = self.query::<command::Voltage>();
+            self.device_id = device_id;
+            match result {
+                Ok(_) => found.push(id),
+                Err(TransactionError::ResponseError(_)) => {}
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(found)
+    }
+
+    /// Address a different RS485 device id for subsequent commands and queries.
+    ///
+    /// Pass `None` to omit the id again. The id is validated like for [`UsbConnection::new`] and
+    /// left unchanged on error.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use kwr103::{command::*, BaudRate, Kwr103, UsbConnection};
+    ///
+    /// let usb = UsbConnection::new("/dev/ttyUSB0", BaudRate::B115200, Some(1)).unwrap();
+    /// let mut kwr103 = Kwr103::from(usb);
+    /// for id in 1..=3 {
+    ///     kwr103.set_device_id(Some(id)).unwrap();
+    ///     kwr103.command(Output(Switch::Off)).unwrap();
+    /// }
+    /// ```
+    pub fn set_device_id(&mut self, id: Option<u8>) -> Result<(), TransactionError> {
+        if let Some(id) = id {
+            validate_device_id(id)?;
+        }
+        self.device_id = DeviceId::from(id);
+        Ok(())
     }
 
     /// Issue a [`Query`] to the power supply.
@@ -174,17 +914,1500 @@ impl Kwr103 {
     ///
     /// # Example
     /// ```no_run
-    /// use kwr103::{command::*, Kwr103, UsbConnection};
+    /// use kwr103::{command::*, BaudRate, Kwr103, UsbConnection};
     ///
-    /// let mut kwr103 = Kwr103::from(UsbConnection::new("/dev/ttyACM0", 115200, None).unwrap());
+    /// let usb = UsbConnection::new("/dev/ttyACM0", BaudRate::B115200, None).unwrap();
+    /// let mut kwr103 = Kwr103::from(usb);
     /// let voltage = kwr103.query::<Voltage>().unwrap();
     /// println!("Voltage = {:.3}V", voltage.0);
     /// ```
     pub fn query<Q: Query>(&mut self) -> Result<Q, TransactionError> {
+        let response = self.exchange::<Q>()?;
+        Ok(Q::parse(&response)?)
+    }
+
+    /// Issue a [`Query`] like [`Kwr103::query`], returning the raw response alongside.
+    ///
+    /// If the response does not parse, the raw response is attached to the error, see
+    /// [`TransactionError::RawResponseError`].
+    ///
+    /// # Example
+    /// ```no_run
+    /// use kwr103::{command::*, BaudRate, Kwr103, UsbConnection};
+    ///
+    /// let usb = UsbConnection::new("/dev/ttyACM0", BaudRate::B115200, None).unwrap();
+    /// let mut kwr103 = Kwr103::from(usb);
+    /// let (status, raw) = kwr103.query_with_raw::<Status>().unwrap();
+    /// println!("{} <- {}", status, raw.escape_ascii());
+    /// ```
+    pub fn query_with_raw<Q: Query>(&mut self) -> Result<(Q, Vec<u8>), TransactionError> {
+        let response = self.exchange::<Q>()?;
+        match Q::parse(&response) {
+            Ok(value) => Ok((value, response)),
+            Err(source) => Err(TransactionError::RawResponseError {
+                source,
+                raw: response,
+            }),
+        }
+    }
+
+    /// Issue a [`Query`] like [`Kwr103::query`], returning the result as a JSON value.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use kwr103::{command::*, BaudRate, Kwr103, UsbConnection};
+    ///
+    /// let usb = UsbConnection::new("/dev/ttyACM0", BaudRate::B115200, None).unwrap();
+    /// let mut kwr103 = Kwr103::from(usb);
+    /// let status = kwr103.query_json::<Status>().unwrap();
+    /// println!("{}", status["voltage"]);
+    /// ```
+    pub fn query_json<Q: Query + serde::Serialize>(
+        &mut self,
+    ) -> Result<serde_json::Value, TransactionError> {
+        Ok(serde_json::json!(self.query::<Q>()?))
+    }
+
+    /// Send the query `Q` and receive the raw response
+    fn exchange<Q: Query>(&mut self) -> Result<Vec<u8>, TransactionError> {
         let payload = Q::serialize(self.device_id);
         self.transport.send(payload.as_slice())?;
 
-        let response = self.transport.receive()?;
-        Ok(Q::parse(&response)?)
+        let response = self.transport.receive_lines(Q::RESPONSE_LINES)?;
+        self.record(&payload, &response);
+        Ok(response)
+    }
+
+    /// Query the power supply like [`Kwr103::query`], waiting up to `timeout` for the response.
+    ///
+    /// The read timeout of the transport is overridden for this single query and restored
+    /// afterwards, even if the query fails.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use std::time::Duration;
+    /// use kwr103::{command::*, BaudRate, Kwr103, UsbConnection};
+    ///
+    /// let usb = UsbConnection::new("/dev/ttyACM0", BaudRate::B115200, None).unwrap();
+    /// let mut kwr103 = Kwr103::from(usb);
+    /// let info: DeviceInfo = kwr103.query_with_timeout(Duration::from_secs(1)).unwrap();
+    /// ```
+    pub fn query_with_timeout<Q: Query>(
+        &mut self,
+        timeout: Duration,
+    ) -> Result<Q, TransactionError> {
+        let previous = self.transport.read_timeout().ok_or_else(|| {
+            TransactionError::InvalidConfiguration(
+                "Transport does not support read timeouts".to_string(),
+            )
+        })?;
+        self.transport.set_read_timeout(timeout)?;
+        let result = self.query::<Q>();
+        self.transport.set_read_timeout(previous)?;
+        result
+    }
+
+    /// Query the power supply like [`Kwr103::query`], falling back to `Q::default()` if the
+    /// response is invalid or incomplete.
+    ///
+    /// This allows to gracefully degrade on models not supporting a particular query. Errors of
+    /// the underlying transport are still propagated.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use kwr103::{command::*, BaudRate, Kwr103, UsbConnection};
+    ///
+    /// let usb = UsbConnection::new("/dev/ttyACM0", BaudRate::B115200, None).unwrap();
+    /// let mut kwr103 = Kwr103::from(usb);
+    /// let limit: PowerLimit = kwr103.query_or_default().unwrap();
+    /// ```
+    pub fn query_or_default<Q: Query + Default>(&mut self) -> Result<Q, TransactionError> {
+        match self.query::<Q>() {
+            Err(TransactionError::ResponseError(_)) => Ok(Q::default()),
+            result => result,
+        }
+    }
+
+    /// Read output state, measurements, settings and regulation mode in a single transaction.
+    ///
+    /// See [`command::FullStatus`] for the order of the concatenated queries.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use kwr103::{BaudRate, Kwr103, UsbConnection};
+    ///
+    /// let usb = UsbConnection::new("/dev/ttyACM0", BaudRate::B115200, None).unwrap();
+    /// let mut kwr103 = Kwr103::from(usb);
+    /// println!("{}", kwr103.full_status().unwrap());
+    /// ```
+    pub fn full_status(&mut self) -> Result<command::FullStatus, TransactionError> {
+        self.query::<command::FullStatus>()
+    }
+
+    /// Report the deviation of the measured output from the settings and the regulation mode.
+    ///
+    /// Settings and measurements are read in a single transaction, see [`command::FullStatus`].
+    ///
+    /// # Example
+    /// ```no_run
+    /// use kwr103::{BaudRate, Kwr103, UsbConnection};
+    ///
+    /// let usb = UsbConnection::new("/dev/ttyACM0", BaudRate::B115200, None).unwrap();
+    /// let mut kwr103 = Kwr103::from(usb);
+    /// println!("{}", kwr103.regulation_report().unwrap());
+    /// ```
+    pub fn regulation_report(&mut self) -> Result<command::RegulationReport, TransactionError> {
+        Ok(command::RegulationReport::from(&self.full_status()?))
+    }
+
+    /// Obtain the details to connect to this power supply via ethernet from its
+    /// [`command::DeviceInfo`], e.g. to configure it via USB and operate it via LAN.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use kwr103::{BaudRate, Kwr103, UsbConnection};
+    ///
+    /// let usb = UsbConnection::new("/dev/ttyACM0", BaudRate::B115200, None).unwrap();
+    /// let mut kwr103 = Kwr103::from(usb);
+    /// let details = kwr103.eth_connection_details().unwrap();
+    /// let mut kwr103 = Kwr103::from(details.open().unwrap());
+    /// ```
+    pub fn eth_connection_details(&mut self) -> Result<eth::ConnectionDetails, TransactionError> {
+        Ok(eth::ConnectionDetails::from(
+            &self.query::<command::DeviceInfo>()?,
+        ))
+    }
+
+    /// Measured output power in watts, computed from a single [`command::Status`] query
+    pub fn power_reading(&mut self) -> Result<f32, TransactionError> {
+        let status = self.query::<command::Status>()?;
+        Ok(status.voltage * status.current)
+    }
+
+    /// Effective load resistance in ohms, computed from a single [`command::Status`] query
+    ///
+    /// Returns [`f32::INFINITY`] if the measured current is below half the current resolution,
+    /// i.e. for an open or disabled output. The resolution is taken from the ratings configured
+    /// by [`Kwr103::with_ratings`], defaulting to 1 mA.
+    pub fn measure_load_resistance(&mut self) -> Result<f32, TransactionError> {
+        let threshold = self
+            .ratings
+            .map_or(0.0005, |ratings| ratings.current_resolution / 2.0);
+        let status = self.query::<command::Status>()?;
+        if status.current.abs() < threshold {
+            return Ok(f32::INFINITY);
+        }
+        Ok(status.voltage / status.current)
+    }
+
+    /// Poll the [`command::Status`] every `poll` and switch the output off on sustained
+    /// over-current.
+    ///
+    /// Blocks until the measured current exceeded `limit` for at least `debounce`, then switches
+    /// the output off and returns the status that tripped the watchdog.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use std::time::Duration;
+    /// use kwr103::{BaudRate, Kwr103, UsbConnection};
+    ///
+    /// let usb = UsbConnection::new("/dev/ttyACM0", BaudRate::B115200, None).unwrap();
+    /// let mut kwr103 = Kwr103::from(usb);
+    /// let tripped = kwr103
+    ///     .run_current_watchdog(1.5, Duration::from_millis(500), Duration::from_millis(100))
+    ///     .unwrap();
+    /// println!("Output switched off at {}", tripped);
+    /// ```
+    pub fn run_current_watchdog(
+        &mut self,
+        limit: f32,
+        debounce: Duration,
+        poll: Duration,
+    ) -> Result<command::Status, TransactionError> {
+        let mut over_current_since: Option<Instant> = None;
+        loop {
+            self.check_cancelled()?;
+            let status = self.query::<command::Status>()?;
+            if status.current > limit {
+                let since = *over_current_since.get_or_insert_with(Instant::now);
+                if since.elapsed() >= debounce {
+                    self.command(command::Output(command::Switch::Off))?;
+                    return Ok(status);
+                }
+            } else {
+                over_current_since = None;
+            }
+            self.sleep_cancellable(poll)?;
+        }
+    }
+
+    /// Poll the measured output voltage every `poll` until it dropped below `below_v`.
+    ///
+    /// Useful after switching the output off, as large output capacitances discharge slowly.
+    /// Fails with [`TransactionError::Timeout`] if the voltage did not drop within `timeout`.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use std::time::Duration;
+    /// use kwr103::{command::*, BaudRate, Kwr103, UsbConnection};
+    ///
+    /// let usb = UsbConnection::new("/dev/ttyACM0", BaudRate::B115200, None).unwrap();
+    /// let mut kwr103 = Kwr103::from(usb);
+    /// kwr103.command(Output(Switch::Off)).unwrap();
+    /// kwr103
+    ///     .wait_output_off(0.5, Duration::from_millis(100), Duration::from_secs(10))
+    ///     .unwrap();
+    /// ```
+    pub fn wait_output_off(
+        &mut self,
+        below_v: f32,
+        poll: Duration,
+        timeout: Duration,
+    ) -> Result<(), TransactionError> {
+        self.measure_until(poll, timeout, |status| status.voltage < below_v)?;
+        Ok(())
+    }
+
+    /// Poll the [`command::Status`] every `poll` until `predicate` holds and return that status.
+    ///
+    /// Fails with [`TransactionError::Timeout`] if the predicate did not hold within `timeout`.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use std::time::Duration;
+    /// use kwr103::{BaudRate, Kwr103, UsbConnection};
+    ///
+    /// let usb = UsbConnection::new("/dev/ttyACM0", BaudRate::B115200, None).unwrap();
+    /// let mut kwr103 = Kwr103::from(usb);
+    /// // wait for the charge current of a battery to taper off
+    /// let status = kwr103
+    ///     .measure_until(Duration::from_secs(10), Duration::from_secs(4 * 3600), |status| {
+    ///         status.current < 0.05
+    ///     })
+    ///     .unwrap();
+    /// ```
+    pub fn measure_until(
+        &mut self,
+        poll: Duration,
+        timeout: Duration,
+        predicate: impl Fn(&command::Status) -> bool,
+    ) -> Result<command::Status, TransactionError> {
+        let start = Instant::now();
+        loop {
+            self.check_cancelled()?;
+            let status = self.query::<command::Status>()?;
+            if predicate(&status) {
+                return Ok(status);
+            }
+            if start.elapsed() >= timeout {
+                return Err(TransactionError::Timeout);
+            }
+            self.sleep_cancellable(poll)?;
+        }
+    }
+
+    /// Take `n` status samples `poll` apart and average the measured voltage and current.
+    ///
+    /// The output power state is taken from the last sample.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use std::time::Duration;
+    /// use kwr103::{BaudRate, Kwr103, UsbConnection};
+    ///
+    /// let usb = UsbConnection::new("/dev/ttyACM0", BaudRate::B115200, None).unwrap();
+    /// let mut kwr103 = Kwr103::from(usb);
+    /// let status = kwr103.measure_averaged(10, Duration::from_millis(100)).unwrap();
+    /// println!("{}", status);
+    /// ```
+    pub fn measure_averaged(
+        &mut self,
+        n: usize,
+        poll: Duration,
+    ) -> Result<command::Status, TransactionError> {
+        if n == 0 {
+            return Err(TransactionError::InvalidConfiguration(
+                "Number of samples to average must be at least 1".to_string(),
+            ));
+        }
+        let mut averaged = command::Status::default();
+        for i in 0..n {
+            if i > 0 {
+                thread::sleep(poll);
+            }
+            let sample = self.query::<command::Status>()?;
+            averaged.voltage += sample.voltage / n as f32;
+            averaged.current += sample.current / n as f32;
+            averaged.power = sample.power;
+        }
+        Ok(averaged)
+    }
+
+    /// Send raw `bytes` to the power supply and return the response split into lines.
+    ///
+    /// Carriage returns are trimmed and trailing empty lines removed. This is intended for
+    /// exploring responses that have no corresponding [`Query`] implementation.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use kwr103::{BaudRate, Kwr103, UsbConnection};
+    ///
+    /// let usb = UsbConnection::new("/dev/ttyACM0", BaudRate::B115200, None).unwrap();
+    /// let mut kwr103 = Kwr103::from(usb);
+    /// for line in kwr103.query_lines(b":SYST:DEVINFO?\n").unwrap() {
+    ///     println!("{}", line);
+    /// }
+    /// ```
+    pub fn query_lines(&mut self, bytes: &[u8]) -> Result<Vec<String>, TransactionError> {
+        self.transport.send(bytes)?;
+        let response = self.transport.receive()?;
+        self.record(bytes, &response);
+
+        let mut lines: Vec<String> = String::from_utf8_lossy(&response)
+            .split('\n')
+            .map(|line| line.trim_end_matches('\r').to_string())
+            .collect();
+        while lines.last().is_some_and(|line| line.is_empty()) {
+            lines.pop();
+        }
+        Ok(lines)
+    }
+
+    /// Continuously poll the [`command::Status`] every `interval`.
+    ///
+    /// Each call to `next` sleeps for `interval` (except for the first one) and performs a single
+    /// status query. The returned iterator never ends, unless cancelled (see
+    /// [`Kwr103::with_cancellation`]), in which case it yields [`TransactionError::Cancelled`]
+    /// once and ends.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use std::time::Duration;
+    /// use kwr103::{BaudRate, Kwr103, UsbConnection};
+    ///
+    /// let usb = UsbConnection::new("/dev/ttyACM0", BaudRate::B115200, None).unwrap();
+    /// let mut kwr103 = Kwr103::from(usb);
+    /// for status in kwr103.status_stream(Duration::from_secs(1)).take(10) {
+    ///     println!("{}", status.unwrap());
+    /// }
+    /// ```
+    pub fn status_stream(
+        &mut self,
+        interval: Duration,
+    ) -> impl Iterator<Item = Result<command::Status, TransactionError>> + '_ {
+        let mut first = true;
+        let mut cancelled = false;
+        std::iter::from_fn(move || {
+            if cancelled {
+                return None;
+            }
+            let mut poll = || {
+                if !first {
+                    self.sleep_cancellable(interval)?;
+                }
+                first = false;
+                self.check_cancelled()?;
+                self.query::<command::Status>()
+            };
+            let status = poll();
+            cancelled = matches!(status, Err(TransactionError::Cancelled));
+            Some(status)
+        })
+    }
+
+    /// Set the output voltage to `pct` percent of the rated maximum voltage.
+    ///
+    /// The rated maximum is taken from [`Kwr103::with_ratings`] if configured, otherwise
+    /// [`command::Ratings`] are queried once from the power supply and retained.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use kwr103::{BaudRate, Kwr103, UsbConnection};
+    ///
+    /// let usb = UsbConnection::new("/dev/ttyACM0", BaudRate::B115200, None).unwrap();
+    /// let mut kwr103 = Kwr103::from(usb);
+    /// kwr103.set_voltage_percent(80.0).unwrap();
+    /// ```
+    pub fn set_voltage_percent(&mut self, pct: f32) -> Result<(), TransactionError> {
+        let fraction = percent_to_fraction(pct)?;
+        let ratings = self.ratings()?;
+        self.command(command::Voltage(ratings.max_voltage * fraction))
+    }
+
+    /// Set the output current to `pct` percent of the rated maximum current.
+    ///
+    /// The rated maximum is obtained like for [`Kwr103::set_voltage_percent`].
+    pub fn set_current_percent(&mut self, pct: f32) -> Result<(), TransactionError> {
+        let fraction = percent_to_fraction(pct)?;
+        let ratings = self.ratings()?;
+        self.command(command::Current(ratings.max_current * fraction))
+    }
+
+    /// The configured ratings, querying and retaining them if not configured yet
+    fn ratings(&mut self) -> Result<command::Ratings, TransactionError> {
+        if let Some(ratings) = self.ratings {
+            return Ok(ratings);
+        }
+        let ratings = self.query::<command::Ratings>()?;
+        self.ratings = Some(ratings);
+        Ok(ratings)
+    }
+
+    /// Whether the remote sense leads are disconnected, see [`command::SenseFault`].
+    pub fn sense_fault(&mut self) -> Result<bool, TransactionError> {
+        Ok(self.query::<command::SenseFault>()?.0)
+    }
+
+    /// Probe which optional queries the power supply supports, see [`command::Capabilities`].
+    ///
+    /// Any error while probing a query, including transport errors, marks it as unsupported.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use kwr103::{BaudRate, Kwr103, UsbConnection};
+    ///
+    /// let usb = UsbConnection::new("/dev/ttyACM0", BaudRate::B115200, None).unwrap();
+    /// let mut kwr103 = Kwr103::from(usb);
+    /// if !kwr103.detect_capabilities().temperature {
+    ///     println!("Temperature readout not supported");
+    /// }
+    /// ```
+    pub fn detect_capabilities(&mut self) -> command::Capabilities {
+        command::Capabilities {
+            over_voltage_protection: self.query::<command::OverVoltageProtection>().is_ok(),
+            over_current_protection: self.query::<command::OverCurrentProtection>().is_ok(),
+            temperature: self.query::<command::Temperature>().is_ok(),
+            status_byte: self.query::<command::Mode>().is_ok(),
+        }
+    }
+
+    /// Clear a latched protection trip, see [`command::ClearProtection`].
+    ///
+    /// This is fire-and-forget, the power supply does not confirm that the protection was cleared.
+    pub fn clear_protection(&mut self) -> Result<(), TransactionError> {
+        self.command(command::ClearProtection)
+    }
+
+    /// Trigger a single audible beep, see [`command::TriggerBeep`].
+    pub fn beep(&mut self) -> Result<(), TransactionError> {
+        self.command(command::TriggerBeep)
+    }
+
+    /// Reset the power supply to its default settings, see [`command::Reset`].
+    ///
+    /// This is fire-and-forget, wait a moment and read back the settings to confirm the reset.
+    pub fn reset(&mut self) -> Result<(), TransactionError> {
+        self.command(command::Reset)
+    }
+
+    /// Switch the output on after waiting for `delay`, e.g. to sequence multiple supplies.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use std::time::Duration;
+    /// use kwr103::{BaudRate, Kwr103, UsbConnection};
+    ///
+    /// let usb = UsbConnection::new("/dev/ttyACM0", BaudRate::B115200, None).unwrap();
+    /// let mut core = Kwr103::from(usb);
+    /// let usb = UsbConnection::new("/dev/ttyACM1", BaudRate::B115200, None).unwrap();
+    /// let mut io = Kwr103::from(usb);
+    /// core.output_on_after(Duration::ZERO).unwrap();
+    /// io.output_on_after(Duration::from_millis(50)).unwrap();
+    /// ```
+    pub fn output_on_after(&mut self, delay: Duration) -> Result<(), TransactionError> {
+        thread::sleep(delay);
+        self.command(command::Output(command::Switch::On))
+    }
+
+    /// Switch the output off after waiting for `delay`, see [`Kwr103::output_on_after`].
+    pub fn output_off_after(&mut self, delay: Duration) -> Result<(), TransactionError> {
+        thread::sleep(delay);
+        self.command(command::Output(command::Switch::Off))
+    }
+
+    /// Run a host timed sequence of voltage and current setpoints.
+    ///
+    /// For each step, the voltage and current settings are sent in a single transmission and held
+    /// for the step's dwell time. The output state is left unchanged, unless `switch_off` is set
+    /// to switch the output off after the last step, or when cancelled (see
+    /// [`Kwr103::with_cancellation`]).
+    ///
+    /// # Example
+    /// ```no_run
+    /// use std::time::Duration;
+    /// use kwr103::{command::*, BaudRate, Kwr103, UsbConnection};
+    ///
+    /// let usb = UsbConnection::new("/dev/ttyACM0", BaudRate::B115200, None).unwrap();
+    /// let mut kwr103 = Kwr103::from(usb);
+    /// let steps = [
+    ///     (Voltage(5.0), Current(0.5), Duration::from_secs(10)),
+    ///     (Voltage(12.0), Current(1.0), Duration::from_secs(30)),
+    /// ];
+    /// kwr103.command(Output(Switch::On)).unwrap();
+    /// kwr103.apply_sequence(&steps, true).unwrap();
+    /// ```
+    pub fn apply_sequence(
+        &mut self,
+        steps: &[(command::Voltage, command::Current, Duration)],
+        switch_off: bool,
+    ) -> Result<(), TransactionError> {
+        for (voltage, current, dwell) in steps {
+            let step = self
+                .check_cancelled()
+                .and_then(|()| {
+                    self.batch()
+                        .command(voltage.clone())
+                        .command(current.clone())
+                        .send()
+                })
+                .and_then(|()| self.sleep_cancellable(*dwell));
+            match step {
+                Err(TransactionError::Cancelled) if switch_off => {
+                    self.command(command::Output(command::Switch::Off))?;
+                    return Err(TransactionError::Cancelled);
+                }
+                step => step?,
+            }
+        }
+        if switch_off {
+            self.command(command::Output(command::Switch::Off))?;
+        }
+        Ok(())
+    }
+
+    /// Toggle the output on and off for a number of `cycles`.
+    ///
+    /// Each cycle switches the output on for `duty * period` and off for the remainder of the
+    /// `period`, so the output is off when this returns. The `duty` cycle must be within (0; 1).
+    ///
+    /// # Example
+    /// ```no_run
+    /// use std::time::Duration;
+    /// use kwr103::{BaudRate, Kwr103, UsbConnection};
+    ///
+    /// let usb = UsbConnection::new("/dev/ttyACM0", BaudRate::B115200, None).unwrap();
+    /// let mut kwr103 = Kwr103::from(usb);
+    /// kwr103.pulse_output(Duration::from_secs(60), 0.25, 10).unwrap();
+    /// ```
+    pub fn pulse_output(
+        &mut self,
+        period: Duration,
+        duty: f32,
+        cycles: usize,
+    ) -> Result<(), TransactionError> {
+        if !(duty > 0.0 && duty < 1.0) {
+            return Err(TransactionError::InvalidConfiguration(
+                "Duty cycle must be in (0; 1)".to_string(),
+            ));
+        }
+        let on_time = period.mul_f32(duty);
+        for _ in 0..cycles {
+            self.check_cancelled()?;
+            self.command(command::Output(command::Switch::On))?;
+            let on = self.sleep_cancellable(on_time);
+            self.command(command::Output(command::Switch::Off))?;
+            on?;
+            self.sleep_cancellable(period - on_time)?;
+        }
+        Ok(())
+    }
+
+    fn send_payload(&mut self, payload: &[u8]) -> Result<(), TransactionError> {
+        self.transport.send(payload)?;
+        self.record(payload, &[]);
+        Ok(())
+    }
+
+    /// Send serialized `commands` concatenated, split into chunks of at most `max_payload` bytes
+    fn send_commands(&mut self, commands: &[Vec<u8>]) -> Result<(), TransactionError> {
+        let max_payload = self.max_payload.unwrap_or(usize::MAX);
+        let mut chunks: Vec<Vec<u8>> = Vec::new();
+        for cmd in commands {
+            match chunks.last_mut() {
+                Some(chunk) if chunk.len() + cmd.len() <= max_payload => chunk.extend(cmd),
+                _ => chunks.push(cmd.clone()),
+            }
+        }
+
+        for (idx, chunk) in chunks.iter().enumerate() {
+            if idx > 0 {
+                thread::sleep(INTER_CHUNK_DELAY);
+            }
+            self.send_payload(chunk)?;
+        }
+        Ok(())
+    }
+
+    fn record(&mut self, request: &[u8], response: &[u8]) {
+        if let Some(log) = self.transaction_log.as_mut() {
+            log.record(request, response);
+        }
+    }
+}
+
+fn check_limit(name: &str, value: f32, max: f32) -> Result<(), TransactionError> {
+    if !(0.0..=max).contains(&value) {
+        return Err(TransactionError::InvalidConfiguration(format!(
+            "{} must be in [0; {}]",
+            name, max
+        )));
+    }
+    Ok(())
+}
+
+fn percent_to_fraction(pct: f32) -> Result<f32, TransactionError> {
+    if !(0.0..=100.0).contains(&pct) {
+        return Err(TransactionError::InvalidConfiguration(
+            "Percentage must be in [0; 100]".to_string(),
+        ));
+    }
+    Ok(pct / 100.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockTransport;
+
+    const IDN: &[u8] = b"KORAD KWR103 V1.0.3 SN:00000001\n";
+
+    #[test]
+    fn set_voltage_percent_of_rating() {
+        let mock = MockTransport::default();
+        mock.respond(IDN);
+        let mut kwr103 = Kwr103::from(mock.clone());
+
+        kwr103.set_voltage_percent(80.0).unwrap();
+        kwr103.set_voltage_percent(50.0).unwrap();
+        assert_eq!(
+            mock.sent(),
+            vec![
+                b"*IDN?\n".to_vec(),
+                b"VSET:48.000\n".to_vec(),
+                b"VSET:30.000\n".to_vec()
+            ]
+        );
+    }
+
+    #[test]
+    fn set_percent_of_configured_rating() {
+        let mock = MockTransport::default();
+        let ratings = command::Ratings {
+            max_voltage: 30.0,
+            max_current: 5.0,
+            voltage_resolution: 0.01,
+            current_resolution: 0.001,
+        };
+        let mut kwr103 = Kwr103::from(mock.clone()).with_ratings(ratings);
+
+        kwr103.set_voltage_percent(50.0).unwrap();
+        kwr103.set_current_percent(10.0).unwrap();
+        assert_eq!(
+            mock.sent(),
+            vec![b"VSET:15.000\n".to_vec(), b"ISET:0.500\n".to_vec()]
+        );
+    }
+
+    #[test]
+    fn set_current_percent_of_rating() {
+        let mock = MockTransport::default();
+        mock.respond(IDN);
+        let mut kwr103 = Kwr103::from(mock.clone());
+
+        kwr103.set_current_percent(10.0).unwrap();
+        assert_eq!(
+            mock.sent(),
+            vec![b"*IDN?\n".to_vec(), b"ISET:1.500\n".to_vec()]
+        );
+    }
+
+    #[test]
+    fn set_percent_out_of_range() {
+        let mock = MockTransport::default();
+        let mut kwr103 = Kwr103::from(mock.clone());
+
+        assert!(kwr103.set_voltage_percent(-1.0).is_err());
+        assert!(kwr103.set_current_percent(100.1).is_err());
+        assert!(mock.sent().is_empty());
+    }
+
+    #[test]
+    fn broadcast_addresses_all_devices() {
+        let mock = MockTransport::default();
+        let mut kwr103 = Kwr103::new(Box::new(mock.clone()), DeviceId::Id(3));
+
+        kwr103
+            .broadcast(command::Output(command::Switch::Off))
+            .unwrap();
+        kwr103
+            .command(command::Output(command::Switch::Off))
+            .unwrap();
+        assert!(kwr103.broadcast(command::UdpPort(0)).is_err());
+        assert_eq!(
+            mock.sent(),
+            vec![b"OUT00:0\n".to_vec(), b"OUT03:0\n".to_vec()]
+        );
+    }
+
+    #[test]
+    fn device_id_serialization() {
+        assert_eq!(DeviceId::None.to_string(), "");
+        assert_eq!(DeviceId::Broadcast.to_string(), "00");
+        assert_eq!(DeviceId::Id(7).to_string(), "07");
+        assert_eq!(DeviceId::Id(42).to_string(), "42");
+        assert_eq!(DeviceId::from(Some(3)), DeviceId::Id(3));
+        assert_eq!(DeviceId::from(None), DeviceId::None);
+    }
+
+    #[test]
+    fn count_complete_response_lines() {
+        assert_eq!(count_response_lines(b""), 0);
+        assert_eq!(count_response_lines(b"42.0"), 0);
+        assert_eq!(count_response_lines(b"42.0\n"), 1);
+        assert_eq!(count_response_lines(b"VSET01?\n42.0\n"), 1);
+        assert_eq!(count_response_lines(b"1\n2.000\n3.0"), 2);
+    }
+
+    #[test]
+    fn query_passes_response_lines_to_transport() {
+        let mock = MockTransport::default();
+        let mut kwr103 = Kwr103::from(mock.clone());
+
+        let _ = kwr103.query::<command::Voltage>();
+        let _ = kwr103.query::<command::Status>();
+        let _ = kwr103.query::<command::DeviceInfo>();
+        assert_eq!(mock.expected_lines(), vec![1, 3, 7]);
+    }
+
+    #[test]
+    fn into_transport_returns_connection() {
+        let mock = MockTransport::default();
+        let kwr103 = Kwr103::from(mock.clone());
+
+        let mut transport = kwr103.into_transport();
+        transport.send(b"VSET?\n").unwrap();
+        assert_eq!(mock.sent(), vec![b"VSET?\n".to_vec()]);
+    }
+
+    #[test]
+    fn set_and_enable_batches_commands() {
+        let mock = MockTransport::default();
+        let mut kwr103 = Kwr103::from(mock.clone());
+
+        kwr103.set_and_enable(12.0, 0.5).unwrap();
+        assert_eq!(
+            mock.sent(),
+            vec![b"VSET:12.000\nISET:0.500\nOUT:1\n".to_vec()]
+        );
+    }
+
+    #[test]
+    fn set_and_enable_validates_configured_ratings() {
+        let mock = MockTransport::default();
+        let ratings = command::Ratings::for_model("KWR103").unwrap();
+        let mut kwr103 = Kwr103::from(mock.clone()).with_ratings(ratings);
+
+        assert!(kwr103.set_and_enable(61.0, 0.5).is_err());
+        assert!(kwr103.set_and_enable(12.0, -0.5).is_err());
+        assert!(mock.sent().is_empty());
+
+        kwr103.set_and_enable(60.0, 15.0).unwrap();
+        assert_eq!(mock.sent().len(), 1);
+    }
+
+    #[test]
+    fn configure_confirms_setpoints() {
+        let mock = MockTransport::default();
+        mock.respond(b"12.000\n");
+        mock.respond(b"0.500\n");
+        let mut kwr103 = Kwr103::from(mock.clone());
+
+        let setpoints = kwr103.configure(12.0, 0.5).unwrap();
+        assert_eq!(
+            setpoints,
+            command::Setpoints {
+                voltage: 12.0,
+                current: 0.5
+            }
+        );
+        assert_eq!(
+            mock.sent(),
+            vec![
+                b"VSET:12.000\nISET:0.500\n".to_vec(),
+                b"VSET?\n".to_vec(),
+                b"ISET?\n".to_vec()
+            ]
+        );
+    }
+
+    #[test]
+    fn configure_detects_mismatching_readback() {
+        let mock = MockTransport::default();
+        mock.respond(b"12.000\n");
+        mock.respond(b"0.100\n");
+        let mut kwr103 = Kwr103::from(mock);
+
+        assert!(kwr103
+            .configure(12.0, 0.5)
+            .is_err_and(|e| matches!(e, TransactionError::VerificationFailed(_))));
+    }
+
+    #[test]
+    fn batch_splits_at_max_payload() {
+        let mock = MockTransport::default();
+        let mut kwr103 = Kwr103::from(mock.clone()).with_max_payload(64);
+
+        let batch = (0..50).fold(kwr103.batch(), |batch, _| {
+            batch.command(command::Voltage(12.0))
+        });
+        batch.send().unwrap();
+
+        let sent = mock.sent();
+        assert_eq!(sent.len(), 10);
+        assert!(sent.iter().all(|chunk| chunk.len() <= 64));
+        assert_eq!(sent.concat(), b"VSET:12.000\n".repeat(50));
+    }
+
+    #[test]
+    fn batch_without_limit_is_sent_at_once() {
+        let mock = MockTransport::default();
+        let mut kwr103 = Kwr103::from(mock.clone());
+
+        kwr103
+            .batch()
+            .command(command::Voltage(12.0))
+            .command(command::Current(0.5))
+            .send()
+            .unwrap();
+        assert_eq!(mock.sent(), vec![b"VSET:12.000\nISET:0.500\n".to_vec()]);
+    }
+
+    #[test]
+    fn command_all_concatenates_payloads() {
+        let mock = MockTransport::default();
+        let mut kwr103 = Kwr103::from(mock.clone());
+
+        kwr103.command_all(&[1, 2], command::Voltage(12.0)).unwrap();
+        assert_eq!(
+            mock.sent(),
+            vec![b"VSET01:12.000\nVSET02:12.000\n".to_vec()]
+        );
+    }
+
+    #[test]
+    fn command_all_rejects_invalid_id() {
+        let mock = MockTransport::default();
+        let mut kwr103 = Kwr103::from(mock.clone());
+
+        assert!(kwr103
+            .command_all(&[1, 100], command::Voltage(12.0))
+            .is_err());
+        assert!(mock.sent().is_empty());
+    }
+
+    #[test]
+    fn pulse_output_toggles_for_each_cycle() {
+        let mock = MockTransport::default();
+        let mut kwr103 = Kwr103::from(mock.clone());
+
+        let period = Duration::from_millis(10);
+        let start = std::time::Instant::now();
+        kwr103.pulse_output(period, 0.5, 3).unwrap();
+
+        assert!(start.elapsed() >= 3 * period);
+        let toggle = [b"OUT:1\n".to_vec(), b"OUT:0\n".to_vec()];
+        assert_eq!(
+            mock.sent(),
+            toggle.iter().cycle().take(6).cloned().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn output_switched_after_delay() {
+        let mock = MockTransport::default();
+        let mut kwr103 = Kwr103::from(mock.clone());
+
+        let delay = Duration::from_millis(10);
+        let start = std::time::Instant::now();
+        kwr103.output_on_after(delay).unwrap();
+        assert!(start.elapsed() >= delay);
+        kwr103.output_off_after(delay).unwrap();
+        assert!(start.elapsed() >= 2 * delay);
+
+        assert_eq!(mock.sent(), vec![b"OUT:1\n".to_vec(), b"OUT:0\n".to_vec()]);
+    }
+
+    #[test]
+    fn apply_sequence_sends_each_step() {
+        let mock = MockTransport::default();
+        let mut kwr103 = Kwr103::from(mock.clone());
+
+        let dwell = Duration::from_millis(5);
+        let steps = [
+            (command::Voltage(5.0), command::Current(0.5), dwell),
+            (command::Voltage(12.0), command::Current(1.0), dwell),
+        ];
+        let start = std::time::Instant::now();
+        kwr103.apply_sequence(&steps, true).unwrap();
+
+        assert!(start.elapsed() >= 2 * dwell);
+        assert_eq!(
+            mock.sent(),
+            vec![
+                b"VSET:5.000\nISET:0.500\n".to_vec(),
+                b"VSET:12.000\nISET:1.000\n".to_vec(),
+                b"OUT:0\n".to_vec(),
+            ]
+        );
+    }
+
+    #[test]
+    fn apply_sequence_switches_off_when_cancelled() {
+        let mock = MockTransport::default();
+        let cancel = Arc::new(AtomicBool::new(true));
+        let mut kwr103 = Kwr103::from(mock.clone()).with_cancellation(cancel);
+
+        let steps = [(command::Voltage(5.0), command::Current(0.5), Duration::ZERO)];
+        let result = kwr103.apply_sequence(&steps, true);
+        assert!(result.is_err_and(|e| matches!(e, TransactionError::Cancelled)));
+        assert_eq!(mock.sent(), vec![b"OUT:0\n".to_vec()]);
+    }
+
+    #[test]
+    fn apply_sequence_cancelled_during_dwell() {
+        let mock = MockTransport::default();
+        let cancel = Arc::new(AtomicBool::new(false));
+        let mut kwr103 = Kwr103::from(mock.clone()).with_cancellation(Arc::clone(&cancel));
+
+        let start = Instant::now();
+        let canceller = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            cancel.store(true, Ordering::Relaxed);
+        });
+        let steps = [(
+            command::Voltage(5.0),
+            command::Current(0.5),
+            Duration::from_secs(30),
+        )];
+        let result = kwr103.apply_sequence(&steps, true);
+        canceller.join().unwrap();
+
+        assert!(result.is_err_and(|e| matches!(e, TransactionError::Cancelled)));
+        assert!(start.elapsed() < Duration::from_secs(1));
+        assert_eq!(
+            mock.sent(),
+            vec![b"VSET:5.000\nISET:0.500\n".to_vec(), b"OUT:0\n".to_vec()]
+        );
+    }
+
+    #[test]
+    fn apply_sequence_keeps_output_state() {
+        let mock = MockTransport::default();
+        let mut kwr103 = Kwr103::from(mock.clone());
+
+        let steps = vec![(command::Voltage(5.0), command::Current(0.5), Duration::ZERO); 3];
+        kwr103.apply_sequence(&steps, false).unwrap();
+        assert_eq!(mock.sent(), vec![b"VSET:5.000\nISET:0.500\n".to_vec(); 3]);
+    }
+
+    #[test]
+    fn pulse_output_rejects_invalid_duty() {
+        let mock = MockTransport::default();
+        let mut kwr103 = Kwr103::from(mock.clone());
+
+        assert!(kwr103.pulse_output(Duration::ZERO, 0.0, 1).is_err());
+        assert!(kwr103.pulse_output(Duration::ZERO, 1.0, 1).is_err());
+        assert!(kwr103.pulse_output(Duration::ZERO, f32::NAN, 1).is_err());
+        assert!(mock.sent().is_empty());
+    }
+
+    #[test]
+    fn status_stream_polls_repeatedly() {
+        let mock = MockTransport::default();
+        mock.respond(b"1\n1.000\n0.100\n");
+        mock.respond(b"1\n2.000\n0.200\n");
+        mock.respond(b"0\n0.000\n0.000\n");
+        let mut kwr103 = Kwr103::from(mock.clone());
+
+        let voltages: Vec<f32> = kwr103
+            .status_stream(Duration::from_millis(1))
+            .take(3)
+            .map(|status| status.unwrap().voltage)
+            .collect();
+        assert_eq!(voltages, vec![1.0, 2.0, 0.0]);
+        assert_eq!(mock.sent().len(), 3);
+    }
+
+    #[test]
+    fn full_status_single_round_trip() {
+        let mock = MockTransport::default();
+        mock.respond(b"1\n4.200\n1.000\n12.000\n1.000\n0\n");
+        let mut kwr103 = Kwr103::from(mock.clone());
+
+        let status = kwr103.full_status().unwrap();
+        assert_eq!(status.mode, command::RegulationMode::ConstantCurrent);
+        assert_eq!(status.voltage, 4.2);
+        assert_eq!(mock.sent().len(), 1);
+        assert_eq!(mock.expected_lines(), vec![6]);
+    }
+
+    #[test]
+    fn regulation_report_in_constant_voltage_mode() {
+        let mock = MockTransport::default();
+        mock.respond(b"1\n12.000\n0.250\n12.000\n1.000\n1\n");
+        let mut kwr103 = Kwr103::from(mock.clone());
+
+        let report = kwr103.regulation_report().unwrap();
+        assert_eq!(report.mode, command::RegulationMode::ConstantVoltage);
+        assert_eq!(report.voltage_error, 0.0);
+        assert_eq!(report.current_error, -0.75);
+        assert_eq!(mock.sent().len(), 1);
+    }
+
+    #[test]
+    fn current_watchdog_trips_on_sustained_over_current() {
+        let mock = MockTransport::default();
+        for current in ["0.500", "2.000", "0.500", "2.000", "2.000"] {
+            mock.respond(format!("1\n12.000\n{}\n", current).as_bytes());
+        }
+        let mut kwr103 = Kwr103::from(mock.clone());
+
+        let poll = Duration::from_millis(5);
+        let tripped = kwr103.run_current_watchdog(1.0, poll, poll).unwrap();
+        assert_eq!(tripped.current, 2.0);
+
+        let sent = mock.sent();
+        assert_eq!(sent.len(), 6);
+        assert_eq!(sent.last().unwrap(), b"OUT:0\n");
+    }
+
+    #[test]
+    fn query_with_timeout_restores_read_timeout() {
+        let mock = MockTransport::default();
+        mock.respond(b"12.000\n");
+        let mut kwr103 = Kwr103::from(mock.clone());
+        let default = kwr103.transport.read_timeout();
+
+        let voltage: command::Voltage = kwr103.query_with_timeout(Duration::from_secs(2)).unwrap();
+        assert_eq!(voltage, command::Voltage(12.0));
+        assert_eq!(
+            mock.read_timeouts(),
+            vec![Duration::from_secs(2), default.unwrap()]
+        );
+        assert_eq!(kwr103.transport.read_timeout(), default);
+
+        assert!(kwr103
+            .query_with_timeout::<command::Voltage>(Duration::from_secs(3))
+            .is_err());
+        assert_eq!(kwr103.transport.read_timeout(), default);
+    }
+
+    #[test]
+    fn query_json_of_status() {
+        let mock = MockTransport::default();
+        mock.respond(b"1\n12.000\n0.500\n");
+        mock.respond(b"");
+        let mut kwr103 = Kwr103::from(mock);
+
+        assert_eq!(
+            kwr103.query_json::<command::Status>().unwrap(),
+            serde_json::json!({ "power": true, "voltage": 12.0, "current": 0.5 })
+        );
+        assert!(kwr103.query_json::<command::Status>().is_err());
+    }
+
+    #[test]
+    fn query_with_raw_returns_response_bytes() {
+        let mock = MockTransport::default();
+        mock.respond(b"VSET?\n12.000\n");
+        mock.respond(b"12.0V\n");
+        let mut kwr103 = Kwr103::from(mock);
+
+        let (voltage, raw) = kwr103.query_with_raw::<command::Voltage>().unwrap();
+        assert_eq!(voltage, command::Voltage(12.0));
+        assert_eq!(raw, b"VSET?\n12.000\n");
+
+        let err = kwr103.query_with_raw::<command::Voltage>().unwrap_err();
+        assert!(matches!(
+            &err,
+            TransactionError::RawResponseError {
+                source: ResponseError::Invalid,
+                raw,
+            } if raw == b"12.0V\n"
+        ));
+        assert!(err.to_string().contains(r#"raw response: "12.0V\n""#));
+    }
+
+    #[test]
+    fn query_or_default_on_garbage_response() {
+        let mock = MockTransport::default();
+        mock.respond(b"\xf8\x80garbage\n");
+        let mut kwr103 = Kwr103::from(mock.clone());
+
+        let limit: command::PowerLimit = kwr103.query_or_default().unwrap();
+        assert_eq!(limit, command::PowerLimit(0.0));
+
+        mock.respond(b"120.0\n");
+        let limit: command::PowerLimit = kwr103.query_or_default().unwrap();
+        assert_eq!(limit, command::PowerLimit(120.0));
+    }
+
+    #[test]
+    fn sense_fault_reports_flag() {
+        let mock = MockTransport::default();
+        mock.respond(b"1\n");
+        mock.respond(b"0\n");
+        let mut kwr103 = Kwr103::from(mock.clone());
+
+        assert!(kwr103.sense_fault().unwrap());
+        assert!(!kwr103.sense_fault().unwrap());
+        assert_eq!(mock.sent()[0], b"SENSEFAULT?\n");
+    }
+
+    #[test]
+    fn detect_capabilities_of_partial_firmware() {
+        let mock = MockTransport::default();
+        mock.respond(b"65.000\n");
+        mock.respond(b"");
+        mock.respond(b"ERR\n");
+        mock.respond(b"1\n");
+        let mut kwr103 = Kwr103::from(mock.clone());
+
+        assert_eq!(
+            kwr103.detect_capabilities(),
+            command::Capabilities {
+                over_voltage_protection: true,
+                over_current_protection: false,
+                temperature: false,
+                status_byte: true,
+            }
+        );
+        assert_eq!(mock.sent().len(), 4);
+    }
+
+    #[test]
+    fn power_reading_from_status() {
+        let mock = MockTransport::default();
+        mock.respond(b"1\n12.000\n0.500\n");
+        let mut kwr103 = Kwr103::from(mock.clone());
+
+        assert_eq!(kwr103.power_reading().unwrap(), 6.0);
+        assert_eq!(mock.sent(), vec![b"OUT?\nVOUT?\nIOUT?\n".to_vec()]);
+    }
+
+    #[test]
+    fn load_resistance_from_status() {
+        let mock = MockTransport::default();
+        mock.respond(b"1\n12.000\n0.500\n");
+        mock.respond(b"1\n12.000\n0.000\n");
+        let mut kwr103 = Kwr103::from(mock);
+
+        assert_eq!(kwr103.measure_load_resistance().unwrap(), 24.0);
+        assert_eq!(kwr103.measure_load_resistance().unwrap(), f32::INFINITY);
+    }
+
+    #[test]
+    fn load_resistance_threshold_from_ratings() {
+        let mock = MockTransport::default();
+        mock.respond(b"1\n12.000\n0.004\n");
+        let ratings = command::Ratings {
+            max_voltage: 30.0,
+            max_current: 5.0,
+            voltage_resolution: 0.01,
+            current_resolution: 0.01,
+        };
+        let mut kwr103 = Kwr103::from(mock).with_ratings(ratings);
+
+        assert_eq!(kwr103.measure_load_resistance().unwrap(), f32::INFINITY);
+    }
+
+    #[test]
+    fn ramp_steps_ascending_and_descending() {
+        assert_eq!(ramp_steps(0.0, 12.0, 4), vec![3.0, 6.0, 9.0, 12.0]);
+        assert_eq!(ramp_steps(12.0, 6.0, 3), vec![10.0, 8.0, 6.0]);
+        assert_eq!(ramp_steps(5.0, 5.0, 2), vec![5.0, 5.0]);
+        assert!(ramp_steps(0.0, 12.0, 0).is_empty());
+    }
+
+    #[test]
+    fn ramp_duration_of_steps() {
+        let delay = Duration::from_millis(250);
+        assert_eq!(ramp_duration(4, delay), Duration::from_secs(1));
+        assert_eq!(ramp_duration(0, delay), Duration::ZERO);
+        assert_eq!(ramp_duration(usize::MAX, Duration::MAX), Duration::MAX);
+    }
+
+    #[test]
+    fn efficiency_computation() {
+        assert_eq!(efficiency(10.0, 8.0), 0.8);
+        assert_eq!(efficiency(10.0, 0.0), 0.0);
+    }
+
+    #[test]
+    fn efficiency_clamps_nonsensical_inputs() {
+        assert_eq!(efficiency(10.0, 12.0), 1.0);
+        assert_eq!(efficiency(10.0, -1.0), 0.0);
+        assert_eq!(efficiency(0.0, 5.0), 0.0);
+        assert_eq!(efficiency(-10.0, 5.0), 0.0);
+        assert_eq!(efficiency(f32::NAN, 5.0), 0.0);
+    }
+
+    #[test]
+    fn scan_device_ids_reports_responding_ids() {
+        let mock = MockTransport::default();
+        mock.respond(b"12.000\n");
+        mock.respond(b"");
+        mock.respond(b"5.000\n");
+        let mut kwr103 = Kwr103::from(mock.clone());
+
+        assert_eq!(kwr103.scan_device_ids(1..=3).unwrap(), vec![1, 3]);
+        assert_eq!(
+            mock.sent(),
+            vec![
+                b"VSET01?\n".to_vec(),
+                b"VSET02?\n".to_vec(),
+                b"VSET03?\n".to_vec()
+            ]
+        );
+        assert_eq!(kwr103.device_id, DeviceId::None);
+    }
+
+    #[test]
+    fn scan_device_ids_rejects_invalid_range() {
+        let mut kwr103 = Kwr103::from(MockTransport::default());
+        assert!(kwr103.scan_device_ids(0..=2).is_err());
+    }
+
+    #[test]
+    fn set_device_id_addresses_subsequent_transactions() {
+        let mock = MockTransport::default();
+        let mut kwr103 = Kwr103::from(mock.clone());
+
+        kwr103.set_device_id(Some(7)).unwrap();
+        kwr103.command(command::Voltage(1.0)).unwrap();
+        kwr103.set_device_id(None).unwrap();
+        kwr103.command(command::Voltage(2.0)).unwrap();
+
+        assert_eq!(
+            mock.sent(),
+            vec![b"VSET07:1.000\n".to_vec(), b"VSET:2.000\n".to_vec()]
+        );
+    }
+
+    #[test]
+    fn set_device_id_rejects_invalid_id() {
+        let mut kwr103 = Kwr103::from(MockTransport::default());
+        kwr103.set_device_id(Some(5)).unwrap();
+
+        assert!(kwr103.set_device_id(Some(0)).is_err());
+        assert!(kwr103.set_device_id(Some(100)).is_err());
+        assert_eq!(kwr103.device_id, DeviceId::Id(5));
+    }
+
+    #[test]
+    fn command_rejects_invalid_value_before_sending() {
+        let mock = MockTransport::default();
+        let mut kwr103 = Kwr103::from(mock.clone());
+
+        let name = command::DeviceName("an overly long label".to_string());
+        assert!(kwr103.command(name.clone()).is_err());
+        assert!(kwr103
+            .batch()
+            .command(command::Voltage(12.0))
+            .command(name)
+            .send()
+            .is_err());
+        assert!(mock.sent().is_empty());
+    }
+
+    #[test]
+    fn eth_connection_details_from_device_info() {
+        let mock = MockTransport::default();
+        mock.respond(b"DHCP:0\nIP:192.168.1.198\nNETMASK:255.255.255.0\nGateWay:192.168.1.1\nMAC:88-06-00-00-ff-ff\nPORT:18191\nBAUDRATE:115200\n");
+        let mut kwr103 = Kwr103::from(mock.clone());
+
+        let details = kwr103.eth_connection_details().unwrap();
+        assert_eq!(details.ip, std::net::Ipv4Addr::new(192, 168, 1, 198));
+        assert_eq!(details.port, 18191);
+        assert_eq!(mock.sent(), vec![b":SYST:DEVINFO?\n".to_vec()]);
+    }
+
+    #[test]
+    fn command_ref_sends_same_command_twice() {
+        let mock = MockTransport::default();
+        let mut kwr103 = Kwr103::from(mock.clone());
+
+        let voltage = command::Voltage(12.0);
+        kwr103.command_ref(&voltage).unwrap();
+        kwr103.command_ref(&voltage).unwrap();
+        assert_eq!(mock.sent(), vec![b"VSET:12.000\n".to_vec(); 2]);
+    }
+
+    #[test]
+    fn close_tears_down_transport() {
+        let mock = MockTransport::default();
+        let kwr103 = Kwr103::from(mock.clone());
+
+        kwr103.close().unwrap();
+        assert!(mock.is_closed());
+    }
+
+    #[test]
+    fn wait_output_off_until_voltage_decayed() {
+        let mock = MockTransport::default();
+        for voltage in ["12.000", "6.000", "2.000", "0.400"] {
+            mock.respond(format!("0\n{}\n0.000\n", voltage).as_bytes());
+        }
+        let mut kwr103 = Kwr103::from(mock.clone());
+
+        let poll = Duration::from_millis(1);
+        kwr103
+            .wait_output_off(0.5, poll, Duration::from_secs(5))
+            .unwrap();
+        assert_eq!(mock.sent().len(), 4);
+    }
+
+    #[test]
+    fn measure_averaged_over_samples() {
+        let mock = MockTransport::default();
+        mock.respond(b"0\n11.000\n1.000\n");
+        mock.respond(b"1\n12.000\n1.500\n");
+        mock.respond(b"1\n13.000\n2.000\n");
+        let mut kwr103 = Kwr103::from(mock.clone());
+
+        let status = kwr103.measure_averaged(3, Duration::ZERO).unwrap();
+        assert_eq!(mock.sent().len(), 3);
+        assert_eq!(status.power, command::Switch::On);
+        assert!((status.voltage - 12.0).abs() < 1e-4);
+        assert!((status.current - 1.5).abs() < 1e-4);
+    }
+
+    #[test]
+    fn measure_averaged_requires_samples() {
+        let mock = MockTransport::default();
+        let mut kwr103 = Kwr103::from(mock.clone());
+        assert!(kwr103.measure_averaged(0, Duration::ZERO).is_err());
+        assert!(mock.sent().is_empty());
+    }
+
+    #[test]
+    fn measure_until_predicate_holds() {
+        let mock = MockTransport::default();
+        for current in ["1.000", "0.500", "0.040", "0.010"] {
+            mock.respond(format!("1\n4.200\n{}\n", current).as_bytes());
+        }
+        let mut kwr103 = Kwr103::from(mock.clone());
+
+        let status = kwr103
+            .measure_until(Duration::from_millis(1), Duration::from_secs(5), |status| {
+                status.current < 0.05
+            })
+            .unwrap();
+        assert_eq!(status.current, 0.04);
+        assert_eq!(mock.sent().len(), 3);
+    }
+
+    #[test]
+    fn measure_until_stops_promptly_when_cancelled() {
+        let mock = MockTransport::default();
+        for _ in 0..1000 {
+            mock.respond(b"1\n12.000\n0.500\n");
+        }
+        let cancel = Arc::new(AtomicBool::new(false));
+        let mut kwr103 = Kwr103::from(mock).with_cancellation(Arc::clone(&cancel));
+
+        let start = Instant::now();
+        let canceller = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            cancel.store(true, Ordering::Relaxed);
+        });
+        let result =
+            kwr103.measure_until(Duration::from_millis(1), Duration::from_secs(10), |_| false);
+        canceller.join().unwrap();
+
+        assert!(result.is_err_and(|e| matches!(e, TransactionError::Cancelled)));
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn status_stream_stops_promptly_when_cancelled() {
+        let mock = MockTransport::default();
+        for _ in 0..1000 {
+            mock.respond(b"1\n12.000\n0.500\n");
+        }
+        let cancel = Arc::new(AtomicBool::new(false));
+        let mut kwr103 = Kwr103::from(mock).with_cancellation(Arc::clone(&cancel));
+
+        let start = Instant::now();
+        let canceller = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            cancel.store(true, Ordering::Relaxed);
+        });
+        let results: Vec<_> = kwr103.status_stream(Duration::from_millis(1)).collect();
+        canceller.join().unwrap();
+
+        let (last, polled) = results.split_last().unwrap();
+        assert!(matches!(last, Err(TransactionError::Cancelled)));
+        assert!(polled.iter().all(Result::is_ok));
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn wait_output_off_times_out() {
+        let mock = MockTransport::default();
+        for _ in 0..100 {
+            mock.respond(b"0\n12.000\n0.000\n");
+        }
+        let mut kwr103 = Kwr103::from(mock);
+
+        let result =
+            kwr103.wait_output_off(0.5, Duration::from_millis(1), Duration::from_millis(5));
+        assert!(result.is_err_and(|e| matches!(e, TransactionError::Timeout)));
+    }
+
+    #[test]
+    fn query_lines_splits_response() {
+        let mock = MockTransport::default();
+        mock.respond(b"DHCP:0\r\nIP:192.168.1.198\nPORT:18190\n\n");
+        let mut kwr103 = Kwr103::from(mock.clone());
+
+        assert_eq!(
+            kwr103.query_lines(b":SYST:DEVINFO?\n").unwrap(),
+            vec!["DHCP:0", "IP:192.168.1.198", "PORT:18190"]
+        );
+        assert_eq!(mock.sent(), vec![b":SYST:DEVINFO?\n".to_vec()]);
+    }
+
+    #[test]
+    fn transaction_log_records_and_evicts() {
+        let mock = MockTransport::default();
+        mock.respond(b"1.000\n");
+        mock.respond(b"2.000\n");
+        let mut kwr103 = Kwr103::from(mock).with_transaction_log(2);
+
+        kwr103.command(command::Voltage(1.0)).unwrap();
+        kwr103.query::<command::Voltage>().unwrap();
+        kwr103.query::<command::Current>().unwrap();
+
+        let log: Vec<_> = kwr103.recent_transactions().cloned().collect();
+        assert_eq!(
+            log,
+            vec![
+                (b"VSET?\n".to_vec(), b"1.000\n".to_vec()),
+                (b"ISET?\n".to_vec(), b"2.000\n".to_vec()),
+            ]
+        );
+
+        let mut kwr103 = Kwr103::from(MockTransport::default()).with_transaction_log(0);
+        kwr103.command(command::Voltage(1.0)).unwrap();
+        kwr103.command(command::Voltage(2.0)).unwrap();
+        assert_eq!(kwr103.recent_transactions().count(), 0);
+    }
+
+    #[test]
+    fn transaction_log_disabled_by_default() {
+        let mut kwr103 = Kwr103::from(MockTransport::default());
+        kwr103.command(command::Voltage(1.0)).unwrap();
+        assert_eq!(kwr103.recent_transactions().count(), 0);
     }
 }
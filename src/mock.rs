@@ -0,0 +1,101 @@
+//! In-memory transport for testing without a physical power supply
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::rc::Rc;
+use std::time::Duration;
+
+use crate::{DeviceId, Kwr103, TransactionError, Transport, TransportKind};
+
+#[derive(Default)]
+struct State {
+    sent: Vec<Vec<u8>>,
+    responses: VecDeque<Vec<u8>>,
+    expected_lines: Vec<usize>,
+    read_timeout: Duration,
+    read_timeouts: Vec<Duration>,
+    closed: bool,
+}
+
+/// A [`Transport`] recording everything sent and replying with queued responses
+///
+/// Clones share their state, so a test can keep a handle after moving the transport into a
+/// [`Kwr103`]. Reports itself as a [`TransportKind::Usb`] connection.
+#[derive(Clone, Default)]
+pub struct MockTransport {
+    state: Rc<RefCell<State>>,
+}
+
+impl MockTransport {
+    /// Queue `bytes` as the response to the next `receive`
+    pub fn respond(&self, bytes: &[u8]) {
+        self.state.borrow_mut().responses.push_back(bytes.to_vec());
+    }
+
+    /// All payloads sent so far
+    pub fn sent(&self) -> Vec<Vec<u8>> {
+        self.state.borrow().sent.clone()
+    }
+
+    /// The response line hints passed to each `receive_lines`
+    pub fn expected_lines(&self) -> Vec<usize> {
+        self.state.borrow().expected_lines.clone()
+    }
+
+    /// The read timeouts set so far, in order
+    pub fn read_timeouts(&self) -> Vec<Duration> {
+        self.state.borrow().read_timeouts.clone()
+    }
+
+    /// Whether `close` was called
+    pub fn is_closed(&self) -> bool {
+        self.state.borrow().closed
+    }
+}
+
+impl Transport for MockTransport {
+    fn kind(&self) -> TransportKind {
+        TransportKind::Usb
+    }
+
+    fn send(&mut self, bytes: &[u8]) -> Result<(), TransactionError> {
+        self.state.borrow_mut().sent.push(bytes.to_vec());
+        Ok(())
+    }
+
+    fn receive(&mut self) -> Result<Vec<u8>, TransactionError> {
+        Ok(self
+            .state
+            .borrow_mut()
+            .responses
+            .pop_front()
+            .unwrap_or_default())
+    }
+
+    fn receive_lines(&mut self, lines: usize) -> Result<Vec<u8>, TransactionError> {
+        self.state.borrow_mut().expected_lines.push(lines);
+        self.receive()
+    }
+
+    fn read_timeout(&self) -> Option<Duration> {
+        Some(self.state.borrow().read_timeout)
+    }
+
+    fn set_read_timeout(&mut self, timeout: Duration) -> Result<(), TransactionError> {
+        let mut state = self.state.borrow_mut();
+        state.read_timeout = timeout;
+        state.read_timeouts.push(timeout);
+        Ok(())
+    }
+
+    fn close(&mut self) -> Result<(), TransactionError> {
+        self.state.borrow_mut().closed = true;
+        Ok(())
+    }
+}
+
+impl From<MockTransport> for Kwr103 {
+    fn from(mock: MockTransport) -> Self {
+        Kwr103::new(Box::new(mock), DeviceId::None)
+    }
+}
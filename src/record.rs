@@ -0,0 +1,229 @@
+//! Recording of transactions for replaying them later, e.g. to reproduce field bugs
+//!
+//! Recordings are line based: each sent payload is written as a line starting with `> ` and each
+//! received response as a line starting with `< `, followed by the bytes in escaped ASCII, e.g.
+//! ```text
+//! > VSET?\n
+//! < 12.000\n
+//! ```
+
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, LineWriter, Write};
+use std::path::Path;
+use std::time::Duration;
+
+use crate::mock::MockTransport;
+use crate::{DeviceId, Kwr103, TransactionError, Transport, TransportKind};
+
+const SENT: &str = "> ";
+const RECEIVED: &str = "< ";
+
+/// A [`Transport`] wrapping another one, recording everything sent and received to a file
+///
+/// Any other writer can be used as well, see [`RecordingTransport::new`] and
+/// [`Kwr103::with_logging_transport`].
+///
+/// # Example
+/// ```no_run
+/// use kwr103::{command::*, record::RecordingTransport, BaudRate, Kwr103, UsbConnection};
+///
+/// let usb = UsbConnection::new("/dev/ttyACM0", BaudRate::B115200, None).unwrap();
+/// let recording = RecordingTransport::create(usb, "session.log").unwrap();
+/// let mut kwr103 = Kwr103::from(recording);
+/// println!("{}", kwr103.query::<Status>().unwrap());
+/// ```
+pub struct RecordingTransport<T: Transport, W: Write = LineWriter<File>> {
+    inner: T,
+    log: W,
+}
+
+impl<T: Transport> RecordingTransport<T> {
+    /// Wrap `inner`, recording to a newly created (or truncated) file at `path`
+    pub fn create<P: AsRef<Path>>(inner: T, path: P) -> io::Result<Self> {
+        Ok(Self::new(inner, LineWriter::new(File::create(path)?)))
+    }
+}
+
+impl<T: Transport, W: Write> RecordingTransport<T, W> {
+    /// Wrap `inner`, recording to `log`, e.g. [`io::stderr`]
+    pub fn new(inner: T, log: W) -> Self {
+        RecordingTransport { inner, log }
+    }
+
+    /// Unwrap the recorded transport
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+
+    fn record(&mut self, prefix: &str, bytes: &[u8]) -> Result<(), TransactionError> {
+        writeln!(self.log, "{}{}", prefix, bytes.escape_ascii())?;
+        Ok(())
+    }
+}
+
+impl<T: Transport, W: Write> Transport for RecordingTransport<T, W> {
+    fn kind(&self) -> TransportKind {
+        self.inner.kind()
+    }
+
+    fn send(&mut self, bytes: &[u8]) -> Result<(), TransactionError> {
+        self.record(SENT, bytes)?;
+        self.inner.send(bytes)
+    }
+
+    fn receive(&mut self) -> Result<Vec<u8>, TransactionError> {
+        let response = self.inner.receive()?;
+        self.record(RECEIVED, &response)?;
+        Ok(response)
+    }
+
+    fn receive_lines(&mut self, lines: usize) -> Result<Vec<u8>, TransactionError> {
+        let response = self.inner.receive_lines(lines)?;
+        self.record(RECEIVED, &response)?;
+        Ok(response)
+    }
+
+    fn read_timeout(&self) -> Option<Duration> {
+        self.inner.read_timeout()
+    }
+
+    fn set_read_timeout(&mut self, timeout: Duration) -> Result<(), TransactionError> {
+        self.inner.set_read_timeout(timeout)
+    }
+
+    fn close(&mut self) -> Result<(), TransactionError> {
+        self.log.flush()?;
+        self.inner.close()
+    }
+}
+
+impl<T: Transport + 'static, W: Write + 'static> From<RecordingTransport<T, W>> for Kwr103 {
+    fn from(recording: RecordingTransport<T, W>) -> Self {
+        Kwr103::new(Box::new(recording), DeviceId::None)
+    }
+}
+
+/// Load a recording written by [`RecordingTransport`] into a [`MockTransport`] replaying the
+/// recorded responses in order
+pub fn load<P: AsRef<Path>>(path: P) -> io::Result<MockTransport> {
+    let mock = MockTransport::default();
+    for line in BufReader::new(File::open(path)?).lines() {
+        let line = line?;
+        if let Some(escaped) = line.strip_prefix(RECEIVED) {
+            mock.respond(&unescape(escaped)?);
+        } else if !line.starts_with(SENT) {
+            return Err(invalid_data(format!(
+                "Unexpected recording line '{}'",
+                line
+            )));
+        }
+    }
+    Ok(mock)
+}
+
+/// Reverse [`u8::escape_ascii`]
+fn unescape(escaped: &str) -> io::Result<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(escaped.len());
+    let mut chars = escaped.bytes();
+    while let Some(b) = chars.next() {
+        if b != b'\\' {
+            bytes.push(b);
+            continue;
+        }
+        match chars.next() {
+            Some(b'n') => bytes.push(b'\n'),
+            Some(b'r') => bytes.push(b'\r'),
+            Some(b't') => bytes.push(b'\t'),
+            Some(c @ (b'\\' | b'\'' | b'"')) => bytes.push(c),
+            Some(b'x') => {
+                let hex = [chars.next(), chars.next()];
+                let hex: Option<Vec<u8>> = hex.into_iter().collect();
+                let byte = hex
+                    .and_then(|hex| String::from_utf8(hex).ok())
+                    .and_then(|hex| u8::from_str_radix(&hex, 16).ok())
+                    .ok_or_else(|| invalid_data(format!("Invalid escape in '{}'", escaped)))?;
+                bytes.push(byte);
+            }
+            _ => return Err(invalid_data(format!("Invalid escape in '{}'", escaped))),
+        }
+    }
+    Ok(bytes)
+}
+
+fn invalid_data(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::{DeviceInfo, Status, Voltage};
+
+    #[test]
+    fn unescape_reverses_escape_ascii() {
+        let bytes = b"VSET01?\n\r\t\\'\"\x00\xf8 ok";
+        let escaped = bytes.escape_ascii().to_string();
+        assert_eq!(unescape(&escaped).unwrap(), bytes);
+        assert!(unescape("\\x4").is_err());
+        assert!(unescape("\\q").is_err());
+    }
+
+    #[derive(Clone, Default)]
+    struct SharedBuffer(std::rc::Rc<std::cell::RefCell<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn recording_to_writer_forwards_and_logs() {
+        let mock = MockTransport::default();
+        mock.respond(b"12.000\n");
+        let log = SharedBuffer::default();
+        let mut kwr103 = Kwr103::from(mock.clone()).with_logging_transport(log.clone());
+
+        assert_eq!(kwr103.query::<Voltage>().unwrap(), Voltage(12.0));
+        kwr103.command(Voltage(5.0)).unwrap();
+        assert_eq!(kwr103.transport_kind(), TransportKind::Usb);
+        kwr103.close().unwrap();
+
+        assert_eq!(
+            mock.sent(),
+            vec![b"VSET?\n".to_vec(), b"VSET:5.000\n".to_vec()]
+        );
+        assert!(mock.is_closed());
+        assert_eq!(
+            String::from_utf8(log.0.borrow().clone()).unwrap(),
+            "> VSET?\\n\n< 12.000\\n\n> VSET:5.000\\n\n"
+        );
+    }
+
+    #[test]
+    fn record_and_replay_round_trip() {
+        let path = std::env::temp_dir().join(format!("kwr103-record-{}.log", std::process::id()));
+
+        let mock = MockTransport::default();
+        mock.respond(b"1\n12.000\n0.500\n");
+        mock.respond(b"\xf8garbage\n");
+        mock.respond(b"");
+        let recording = RecordingTransport::create(mock, &path).unwrap();
+        let mut kwr103 = Kwr103::from(recording);
+        let status = kwr103.query::<Status>().unwrap();
+        assert!(kwr103.query::<Voltage>().is_err());
+        assert!(kwr103.query::<DeviceInfo>().is_err());
+        kwr103.close().unwrap();
+
+        let replay = load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let mut kwr103 = Kwr103::from(replay);
+        assert_eq!(kwr103.query::<Status>().unwrap(), status);
+        assert_eq!(kwr103.transport.receive().unwrap(), b"\xf8garbage\n");
+        assert_eq!(kwr103.transport.receive().unwrap(), b"");
+    }
+}
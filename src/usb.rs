@@ -1,89 +1,377 @@
 //! Serial communication for USB connected power supplies
 
+use std::fmt;
+use std::io::{Read, Write};
+use std::str::FromStr;
+use std::thread;
 use std::time::Duration;
 
+use serde::{Serialize, Serializer};
 use serialport;
+pub use serialport::{FlowControl, Parity, StopBits};
 
-use crate::{Kwr103, ResponseError, TransactionError, Transport};
+use crate::command::Voltage;
+use crate::{
+    count_response_lines, validate_device_id, DeviceId, Kwr103, Query, ResponseError,
+    TransactionError, Transport, TransportKind,
+};
+
+/// Serial baud rates supported by the KWR103
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum BaudRate {
+    /// 9600 baud
+    B9600 = 9600,
+    /// 19200 baud
+    B19200 = 19200,
+    /// 38400 baud
+    B38400 = 38400,
+    /// 57600 baud
+    B57600 = 57600,
+    /// 115200 baud
+    B115200 = 115200,
+}
+
+impl BaudRate {
+    /// All baud rates supported by the KWR103, in ascending order
+    pub const ALL: [BaudRate; 5] = [
+        BaudRate::B9600,
+        BaudRate::B19200,
+        BaudRate::B38400,
+        BaudRate::B57600,
+        BaudRate::B115200,
+    ];
+}
+
+impl TryFrom<u32> for BaudRate {
+    type Error = TransactionError;
+
+    fn try_from(baud: u32) -> Result<Self, Self::Error> {
+        BaudRate::ALL
+            .into_iter()
+            .find(|&rate| u32::from(rate) == baud)
+            .ok_or_else(|| {
+                let supported: Vec<String> = BaudRate::ALL.iter().map(|b| b.to_string()).collect();
+                TransactionError::InvalidConfiguration(format!(
+                    "Unsupported baud rate {} (must be one of {})",
+                    baud,
+                    supported.join(", ")
+                ))
+            })
+    }
+}
+
+impl From<BaudRate> for u32 {
+    fn from(baud: BaudRate) -> Self {
+        baud as u32
+    }
+}
+
+impl FromStr for BaudRate {
+    type Err = TransactionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let baud: u32 = s.parse().map_err(|_| {
+            TransactionError::InvalidConfiguration(format!("Invalid baud rate '{}'", s))
+        })?;
+        BaudRate::try_from(baud)
+    }
+}
+
+impl Serialize for BaudRate {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u32(u32::from(*self))
+    }
+}
+
+impl fmt::Display for BaudRate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", *self as u32)
+    }
+}
 
 /// Communication channel for a serial/USB connected power supply
 pub struct UsbConnection {
     serial: Box<dyn serialport::SerialPort>,
+    settings: serialport::SerialPortBuilder,
     device_id: Option<u8>,
+    auto_reopen: bool,
+    inter_command_delay: Duration,
+    usb_serial_number: Option<String>,
 }
 
 impl UsbConnection {
     /// Create a new USB communication channel
+    ///
+    /// The serial port is opened with no parity, one stop bit and no flow control. Use
+    /// [`UsbConnection::builder`] for different framing.
     pub fn new(
         port_name: &str,
-        baud_rate: u32,
+        baud_rate: BaudRate,
         device_id: Option<u8>,
     ) -> Result<Self, TransactionError> {
-        if let Some(id) = device_id {
-            if id == 0 || id > 99 {
-                return Err(TransactionError::InvalidConfiguration(
-                    "KWR103 RS485 device id must be in [1; 99]".to_string(),
-                ));
-            }
+        Self::builder(port_name, baud_rate)
+            .device_id(device_id)
+            .open()
+    }
+
+    /// Configure a USB communication channel with non-default serial framing
+    ///
+    /// ```no_run
+    /// use kwr103::{usb::Parity, BaudRate, UsbConnection};
+    ///
+    /// let usb = UsbConnection::builder("/dev/ttyUSB0", BaudRate::B9600)
+    ///     .parity(Parity::Even)
+    ///     .device_id(Some(3))
+    ///     .open()
+    ///     .unwrap();
+    /// ```
+    pub fn builder(port_name: &str, baud_rate: BaudRate) -> UsbConnectionBuilder {
+        UsbConnectionBuilder {
+            settings: serialport::new(port_name, baud_rate.into())
+                .timeout(Duration::from_millis(150))
+                .parity(Parity::None)
+                .stop_bits(StopBits::One)
+                .flow_control(FlowControl::None),
+            device_id: None,
+            inter_command_delay: Duration::ZERO,
         }
+    }
 
-        let serial = serialport::new(port_name, baud_rate)
-            .timeout(Duration::from_millis(150))
-            .parity(serialport::Parity::None)
-            .stop_bits(serialport::StopBits::One)
-            .open()?;
+    /// Re-run device discovery and reopen the serial port of the same power supply
+    ///
+    /// After replugging, the power supply may show up under a different device path, e.g.
+    /// `/dev/ttyACM1` instead of `/dev/ttyACM0`. The port is identified by the USB serial number
+    /// recorded when opening the connection. If it is unknown or not found, a port is only
+    /// reopened if it is the single matching one, as multiple attached power supplies cannot be
+    /// told apart. Serial settings and device id are retained.
+    pub fn reopen(&mut self) -> Result<(), TransactionError> {
+        let port_name = reopen_port(
+            serialport::available_ports(),
+            self.usb_serial_number.as_deref(),
+        )?;
+        self.serial = self.settings.clone().path(port_name).open()?;
+        Ok(())
+    }
+
+    /// Automatically [`reopen`](UsbConnection::reopen) the connection if sending or receiving
+    /// fails
+    ///
+    /// A failed send is retried once after reopening, a failed receive is still reported since
+    /// the response is lost.
+    pub fn with_auto_reopen(mut self, auto_reopen: bool) -> Self {
+        self.auto_reopen = auto_reopen;
+        self
+    }
 
-        Ok(Self { serial, device_id })
+    fn write(&mut self, bytes: &[u8]) -> Result<(), TransactionError> {
+        write_delayed(&mut *self.serial, bytes, self.inter_command_delay)
+    }
+
+    /// Verify that the power supply answers sensibly at the configured baud rate
+    ///
+    /// Opening the serial port at a baud rate not matching the power supply succeeds, but yields
+    /// garbage responses. This opt-in check queries the voltage setting and fails with
+    /// [`TransactionError::InvalidConfiguration`] if the response does not parse.
+    ///
+    /// ```no_run
+    /// use kwr103::{BaudRate, UsbConnection};
+    ///
+    /// let usb = UsbConnection::new("/dev/ttyACM0", BaudRate::B115200, None)
+    ///     .and_then(UsbConnection::probe)
+    ///     .unwrap();
+    /// ```
+    pub fn probe(mut self) -> Result<Self, TransactionError> {
+        let device_id = self.device_id.into();
+        probe_baud_rate(&mut self, device_id)?;
+        Ok(self)
     }
 }
 
+/// Builder for a [`UsbConnection`] with custom serial framing
+///
+/// Created by [`UsbConnection::builder`].
+#[derive(Debug, Clone)]
+pub struct UsbConnectionBuilder {
+    settings: serialport::SerialPortBuilder,
+    device_id: Option<u8>,
+    inter_command_delay: Duration,
+}
+
+impl UsbConnectionBuilder {
+    /// Set the parity bit mode (default: none)
+    pub fn parity(mut self, parity: Parity) -> Self {
+        self.settings = self.settings.parity(parity);
+        self
+    }
+
+    /// Set the number of stop bits (default: one)
+    pub fn stop_bits(mut self, stop_bits: StopBits) -> Self {
+        self.settings = self.settings.stop_bits(stop_bits);
+        self
+    }
+
+    /// Set the flow control mode (default: none)
+    pub fn flow_control(mut self, flow_control: FlowControl) -> Self {
+        self.settings = self.settings.flow_control(flow_control);
+        self
+    }
+
+    /// Set the optional RS485 device ID
+    pub fn device_id(mut self, device_id: Option<u8>) -> Self {
+        self.device_id = device_id;
+        self
+    }
+
+    /// Set a delay to wait before each write (default: zero)
+    ///
+    /// Some RS485 adapters drop bytes of back-to-back writes and require a gap in between.
+    pub fn inter_command_delay(mut self, delay: Duration) -> Self {
+        self.inter_command_delay = delay;
+        self
+    }
+
+    /// Open the serial port and create the [`UsbConnection`]
+    pub fn open(self) -> Result<UsbConnection, TransactionError> {
+        if let Some(id) = self.device_id {
+            validate_device_id(id)?;
+        }
+
+        let serial = self.settings.clone().open()?;
+        let usb_serial_number = serial
+            .name()
+            .and_then(|name| usb_serial_number(serialport::available_ports().ok()?, &name));
+        Ok(UsbConnection {
+            serial,
+            settings: self.settings,
+            device_id: self.device_id,
+            auto_reopen: false,
+            inter_command_delay: self.inter_command_delay,
+            usb_serial_number,
+        })
+    }
+}
+
+/// Write `bytes` to `writer` after waiting for `delay`
+fn write_delayed<W: Write + ?Sized>(
+    writer: &mut W,
+    bytes: &[u8],
+    delay: Duration,
+) -> Result<(), TransactionError> {
+    if !delay.is_zero() {
+        thread::sleep(delay);
+    }
+    let written = writer
+        .write(bytes)
+        .map_err(|err| TransactionError::from_io(err, TransactionError::from))?;
+    if written != bytes.len() {
+        return Err(TransactionError::RequestError);
+    }
+    Ok(())
+}
+
+fn probe_baud_rate(
+    transport: &mut dyn Transport,
+    device_id: DeviceId,
+) -> Result<(), TransactionError> {
+    transport.send(&<Voltage as Query>::serialize(device_id))?;
+    let response = transport.receive_lines(<Voltage as Query>::RESPONSE_LINES)?;
+    <Voltage as Query>::parse(&response).map_err(|_| {
+        TransactionError::InvalidConfiguration("No valid response, baud rate mismatch?".to_string())
+    })?;
+    Ok(())
+}
+
 impl Transport for UsbConnection {
+    fn kind(&self) -> TransportKind {
+        TransportKind::Usb
+    }
+
     fn send(&mut self, bytes: &[u8]) -> Result<(), TransactionError> {
-        if self.serial.write(bytes)? != bytes.len() {
-            return Err(TransactionError::RequestError);
+        match self.write(bytes) {
+            Err(_) if self.auto_reopen => {
+                self.reopen()?;
+                self.write(bytes)
+            }
+            result => result,
         }
-        Ok(())
     }
 
     fn receive(&mut self) -> Result<Vec<u8>, TransactionError> {
-        let mut received: Vec<u8> = Vec::new();
-        let mut is_done = false;
-        while !is_done {
-            let mut buf: Vec<u8> = vec![0; 512];
-            match self.serial.read(buf.as_mut_slice()) {
-                Ok(count) => {
-                    received.extend(buf.drain(..count));
-                }
-                Err(ref e) if e.kind() == std::io::ErrorKind::TimedOut => {
-                    is_done = true;
-                }
-                Err(_) => {
-                    return Err(TransactionError::ResponseError(ResponseError::Incomplete));
-                }
-            };
+        let result = read_response(&mut *self.serial, None);
+        if result.is_err() && self.auto_reopen {
+            self.reopen()?;
+        }
+        result
+    }
+
+    fn receive_lines(&mut self, lines: usize) -> Result<Vec<u8>, TransactionError> {
+        let result = read_response(&mut *self.serial, Some(lines));
+        if result.is_err() && self.auto_reopen {
+            self.reopen()?;
         }
-        Ok(received)
+        result
+    }
+
+    fn read_timeout(&self) -> Option<Duration> {
+        Some(self.serial.timeout())
+    }
+
+    /// Also applies when the connection is reopened
+    fn set_read_timeout(&mut self, timeout: Duration) -> Result<(), TransactionError> {
+        self.serial.set_timeout(timeout)?;
+        self.settings = self.settings.clone().timeout(timeout);
+        Ok(())
     }
+
+    /// Flush pending output to the serial port
+    fn close(&mut self) -> Result<(), TransactionError> {
+        self.serial.flush().map_err(serialport::Error::from)?;
+        Ok(())
+    }
+}
+
+/// Read from `reader` until timeout or, if given, until `lines` response lines were received
+fn read_response<R: Read + ?Sized>(
+    reader: &mut R,
+    lines: Option<usize>,
+) -> Result<Vec<u8>, TransactionError> {
+    let mut received: Vec<u8> = Vec::new();
+    let mut is_done = false;
+    while !is_done {
+        let mut buf: Vec<u8> = vec![0; 512];
+        match reader.read(buf.as_mut_slice()) {
+            Ok(count) => {
+                received.extend(buf.drain(..count));
+                is_done = lines.is_some_and(|n| count_response_lines(&received) >= n);
+            }
+            Err(ref e) if e.kind() == std::io::ErrorKind::TimedOut => {
+                is_done = true;
+            }
+            Err(err) => {
+                return Err(TransactionError::from_io(err, |_| {
+                    ResponseError::Incomplete.into()
+                }));
+            }
+        };
+    }
+    Ok(received)
 }
 
 impl From<UsbConnection> for Kwr103 {
     fn from(con: UsbConnection) -> Self {
         let device_id = con.device_id;
-        Kwr103 {
-            transport: Box::new(con),
-            device_id,
-        }
+        Kwr103::new(Box::new(con), device_id.into())
     }
 }
 
 /// Connection details for a serial connected power supply
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct ConnectionDetails {
     /// The serial device, e.g. `/dev/ttyACM0`
     pub serial: String,
 
     /// Baudrate for the serial connection
-    pub baud_rate: u32,
+    pub baud_rate: BaudRate,
 
     /// Optional RS485 device ID
     pub device_id: Option<u8>,
@@ -97,32 +385,398 @@ impl ConnectionDetails {
 }
 
 /// Discover serial connected devices
-pub fn find_devices(baud_rate: u32, device_id: Option<u8>) -> Vec<ConnectionDetails> {
-    serialport::available_ports()
-        .unwrap_or_default()
+///
+/// Enumeration errors are treated as if no device was found, use [`try_find_devices`] to surface
+/// them instead.
+pub fn find_devices(baud_rate: BaudRate, device_id: Option<u8>) -> Vec<ConnectionDetails> {
+    try_find_devices(baud_rate, device_id).unwrap_or_default()
+}
+
+/// Discover serial connected devices, failing if the serial ports cannot be enumerated
+pub fn try_find_devices(
+    baud_rate: BaudRate,
+    device_id: Option<u8>,
+) -> Result<Vec<ConnectionDetails>, TransactionError> {
+    matching_devices(serialport::available_ports(), baud_rate, device_id)
+}
+
+fn matching_devices(
+    ports: serialport::Result<Vec<serialport::SerialPortInfo>>,
+    baud_rate: BaudRate,
+    device_id: Option<u8>,
+) -> Result<Vec<ConnectionDetails>, TransactionError> {
+    Ok(ports?
         .into_iter()
-        .filter(|info| match &info.port_type {
-            serialport::SerialPortType::UsbPort(usb) => usb.vid == 0x0416 && usb.pid == 0x5011,
-            _ => false,
-        })
+        .filter(is_supported_port)
         .map(|port| ConnectionDetails {
             serial: port.port_name,
             baud_rate,
             device_id,
         })
-        .collect()
+        .collect())
+}
+
+/// The USB serial number of the port named `port_name`
+fn usb_serial_number(ports: Vec<serialport::SerialPortInfo>, port_name: &str) -> Option<String> {
+    ports
+        .into_iter()
+        .find(|port| port.port_name == port_name)
+        .and_then(|port| match port.port_type {
+            serialport::SerialPortType::UsbPort(usb) => usb.serial_number,
+            _ => None,
+        })
+}
+
+/// Select the matching port with USB serial number `serial_number`, or the single matching port
+fn reopen_port(
+    ports: serialport::Result<Vec<serialport::SerialPortInfo>>,
+    serial_number: Option<&str>,
+) -> Result<String, TransactionError> {
+    let candidates: Vec<_> = ports?.into_iter().filter(is_supported_port).collect();
+    let same_serial = candidates.iter().find(|port| match &port.port_type {
+        serialport::SerialPortType::UsbPort(usb) => {
+            serial_number.is_some() && usb.serial_number.as_deref() == serial_number
+        }
+        _ => false,
+    });
+    match (same_serial, candidates.as_slice()) {
+        (Some(port), _) | (None, [port]) => Ok(port.port_name.clone()),
+        (None, []) => Err(TransactionError::InvalidConfiguration(
+            "No matching serial device found to reopen".to_string(),
+        )),
+        (None, _) => Err(TransactionError::InvalidConfiguration(
+            "Multiple matching serial devices found, cannot tell which one to reopen".to_string(),
+        )),
+    }
+}
+
+fn is_supported_port(info: &serialport::SerialPortInfo) -> bool {
+    match &info.port_type {
+        serialport::SerialPortType::UsbPort(usb) => usb.vid == 0x0416 && usb.pid == 0x5011,
+        _ => false,
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::mock::MockTransport;
+    use std::collections::VecDeque;
+    use std::io;
+
+    /// Serial source yielding one chunk per read, timing out once exhausted
+    struct ChunkedSerial(VecDeque<&'static [u8]>);
+
+    impl Read for ChunkedSerial {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            match self.0.pop_front() {
+                Some(chunk) => {
+                    buf[..chunk.len()].copy_from_slice(chunk);
+                    Ok(chunk.len())
+                }
+                None => Err(io::ErrorKind::TimedOut.into()),
+            }
+        }
+    }
+
+    /// Serial port whose peer has gone away
+    struct DisconnectedSerial(io::ErrorKind);
+
+    impl Read for DisconnectedSerial {
+        fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+            Err(self.0.into())
+        }
+    }
+
+    impl Write for DisconnectedSerial {
+        fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+            Err(self.0.into())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn disconnected_serial_reports_not_connected() {
+        for kind in [io::ErrorKind::NotConnected, io::ErrorKind::BrokenPipe] {
+            let mut serial = DisconnectedSerial(kind);
+            assert!(write_delayed(&mut serial, b"VSET?\n", Duration::ZERO)
+                .is_err_and(|e| matches!(e, TransactionError::NotConnected)));
+            assert!(read_response(&mut serial, None)
+                .is_err_and(|e| matches!(e, TransactionError::NotConnected)));
+        }
+
+        let mut serial = DisconnectedSerial(io::ErrorKind::Other);
+        assert!(read_response(&mut serial, None).is_err_and(|e| matches!(
+            e,
+            TransactionError::ResponseError(ResponseError::Incomplete)
+        )));
+    }
+
+    #[test]
+    fn read_response_until_timeout() {
+        let mut serial = ChunkedSerial(VecDeque::from([&b"1\n2.0"[..], b"00\n", b"3.000\n"]));
+        assert_eq!(
+            read_response(&mut serial, None).unwrap(),
+            b"1\n2.000\n3.000\n"
+        );
+    }
+
+    #[test]
+    fn read_response_stops_at_expected_lines() {
+        let mut serial = ChunkedSerial(VecDeque::from([&b"1\n2.0"[..], b"00\n", b"3.000\n"]));
+        assert_eq!(read_response(&mut serial, Some(2)).unwrap(), b"1\n2.000\n");
+        assert_eq!(serial.0.len(), 1);
+    }
+
+    #[test]
+    fn read_response_skips_echoed_query() {
+        let mut serial = ChunkedSerial(VecDeque::from([&b"VSET?\n"[..], b"42.0\n", b"junk"]));
+        assert_eq!(
+            read_response(&mut serial, Some(1)).unwrap(),
+            b"VSET?\n42.0\n"
+        );
+    }
 
     #[test]
     fn creating_new_usb_connection_with_invalid_id() {
-        let lo = UsbConnection::new("/dev/ttyACM0", 115200, Some(0));
+        let lo = UsbConnection::new("/dev/ttyACM0", BaudRate::B115200, Some(0));
         assert!(lo.is_err_and(|e| e.to_string().contains("RS485 device id")));
 
-        let hi = UsbConnection::new("/dev/ttyACM0", 115200, Some(100));
+        let hi = UsbConnection::new("/dev/ttyACM0", BaudRate::B115200, Some(100));
         assert!(hi.is_err_and(|e| e.to_string().contains("RS485 device id")));
     }
+
+    #[test]
+    fn builder_applies_serial_framing() {
+        let builder = UsbConnection::builder("/dev/ttyUSB0", BaudRate::B9600)
+            .parity(Parity::Even)
+            .stop_bits(StopBits::Two);
+        assert_eq!(
+            builder.settings,
+            serialport::new("/dev/ttyUSB0", 9600)
+                .timeout(Duration::from_millis(150))
+                .parity(Parity::Even)
+                .stop_bits(StopBits::Two)
+                .flow_control(FlowControl::None)
+        );
+    }
+
+    #[test]
+    fn builder_sets_inter_command_delay() {
+        let builder = UsbConnection::builder("/dev/ttyUSB0", BaudRate::B9600);
+        assert_eq!(builder.inter_command_delay, Duration::ZERO);
+
+        let builder = builder.inter_command_delay(Duration::from_millis(20));
+        assert_eq!(builder.inter_command_delay, Duration::from_millis(20));
+    }
+
+    #[test]
+    fn write_waits_for_inter_command_delay() {
+        let mut written = Vec::new();
+        let start = std::time::Instant::now();
+        write_delayed(&mut written, b"VSET:1.000\n", Duration::from_millis(20)).unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(20));
+        assert_eq!(written, b"VSET:1.000\n");
+    }
+
+    #[test]
+    fn builder_rejects_invalid_id() {
+        let result = UsbConnection::builder("/dev/ttyUSB0", BaudRate::B9600)
+            .device_id(Some(0))
+            .open();
+        assert!(result.is_err_and(|e| e.to_string().contains("RS485 device id")));
+    }
+
+    #[test]
+    fn baud_rate_conversions() {
+        assert_eq!(BaudRate::try_from(9600).unwrap(), BaudRate::B9600);
+        assert_eq!(BaudRate::try_from(115200).unwrap(), BaudRate::B115200);
+        assert!(BaudRate::try_from(4800).is_err());
+        assert!(BaudRate::try_from(0).is_err());
+
+        assert_eq!("57600".parse::<BaudRate>().unwrap(), BaudRate::B57600);
+        assert!("115201".parse::<BaudRate>().is_err());
+        assert!("fast".parse::<BaudRate>().is_err());
+
+        assert_eq!(BaudRate::B38400.to_string(), "38400");
+        assert_eq!(u32::from(BaudRate::B19200), 19200);
+    }
+
+    #[test]
+    fn all_baud_rates_round_trip() {
+        let rates: Vec<u32> = BaudRate::ALL.into_iter().map(u32::from).collect();
+        assert_eq!(rates, [9600, 19200, 38400, 57600, 115200]);
+        for rate in BaudRate::ALL {
+            assert_eq!(BaudRate::try_from(u32::from(rate)).unwrap(), rate);
+        }
+        assert!(BaudRate::try_from(4800).is_err_and(|e| e
+            .to_string()
+            .ends_with("(must be one of 9600, 19200, 38400, 57600, 115200)")));
+    }
+
+    fn usb_port(name: &str, vid: u16, pid: u16) -> serialport::SerialPortInfo {
+        usb_port_with_serial(name, vid, pid, None)
+    }
+
+    fn usb_port_with_serial(
+        name: &str,
+        vid: u16,
+        pid: u16,
+        serial_number: Option<&str>,
+    ) -> serialport::SerialPortInfo {
+        serialport::SerialPortInfo {
+            port_name: name.to_string(),
+            port_type: serialport::SerialPortType::UsbPort(serialport::UsbPortInfo {
+                vid,
+                pid,
+                serial_number: serial_number.map(String::from),
+                manufacturer: None,
+                product: None,
+            }),
+        }
+    }
+
+    #[test]
+    fn connection_details_dedup_in_set() {
+        let details = |serial: &str, device_id| ConnectionDetails {
+            serial: serial.to_string(),
+            baud_rate: BaudRate::B115200,
+            device_id,
+        };
+        let set: std::collections::HashSet<_> = [
+            details("/dev/ttyACM0", None),
+            details("/dev/ttyACM0", None),
+            details("/dev/ttyACM0", Some(1)),
+            details("/dev/ttyACM1", None),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(set.len(), 3);
+    }
+
+    #[test]
+    fn matching_devices_filters_by_vid_pid() {
+        let ports = vec![
+            usb_port("/dev/ttyACM0", 0x0416, 0x5011),
+            usb_port("/dev/ttyUSB0", 0x0403, 0x6001),
+        ];
+
+        let devices = matching_devices(Ok(ports), BaudRate::B115200, Some(3)).unwrap();
+        assert_eq!(devices.len(), 1);
+        assert_eq!(devices[0].serial, "/dev/ttyACM0");
+        assert_eq!(devices[0].device_id, Some(3));
+    }
+
+    #[test]
+    fn matching_devices_propagates_enumeration_error() {
+        let error = serialport::Error::new(serialport::ErrorKind::NoDevice, "permission denied");
+        let result = matching_devices(Err(error), BaudRate::B115200, None);
+        assert!(result.is_err_and(|e| matches!(e, TransactionError::UsbConnection(_))));
+    }
+
+    #[test]
+    fn reopen_picks_port_with_same_serial_number() {
+        let ports = || {
+            vec![
+                usb_port("/dev/ttyUSB0", 0x0403, 0x6001),
+                usb_port_with_serial("/dev/ttyACM1", 0x0416, 0x5011, Some("0001")),
+                usb_port_with_serial("/dev/ttyACM2", 0x0416, 0x5011, Some("0002")),
+            ]
+        };
+        assert_eq!(
+            reopen_port(Ok(ports()), Some("0002")).unwrap(),
+            "/dev/ttyACM2"
+        );
+        assert!(reopen_port(Ok(ports()), Some("0003")).is_err());
+        assert!(reopen_port(Ok(ports()), None).is_err());
+    }
+
+    #[test]
+    fn reopen_picks_single_matching_port() {
+        let ports = || {
+            vec![
+                usb_port("/dev/ttyUSB0", 0x0403, 0x6001),
+                usb_port("/dev/ttyACM1", 0x0416, 0x5011),
+            ]
+        };
+        assert_eq!(reopen_port(Ok(ports()), None).unwrap(), "/dev/ttyACM1");
+        assert_eq!(
+            reopen_port(Ok(ports()), Some("0001")).unwrap(),
+            "/dev/ttyACM1"
+        );
+    }
+
+    #[test]
+    fn reopen_fails_without_matching_port() {
+        let ports = vec![usb_port("/dev/ttyUSB0", 0x0403, 0x6001)];
+        let result = reopen_port(Ok(ports), None);
+        assert!(result.is_err_and(|e| e.to_string().contains("to reopen")));
+    }
+
+    #[test]
+    fn usb_serial_number_of_port() {
+        let ports = vec![
+            usb_port("/dev/ttyACM0", 0x0416, 0x5011),
+            usb_port_with_serial("/dev/ttyACM1", 0x0416, 0x5011, Some("0001")),
+        ];
+        assert_eq!(
+            usb_serial_number(ports.clone(), "/dev/ttyACM1").as_deref(),
+            Some("0001")
+        );
+        assert_eq!(usb_serial_number(ports.clone(), "/dev/ttyACM0"), None);
+        assert_eq!(usb_serial_number(ports, "/dev/ttyACM9"), None);
+    }
+
+    /// Connection on one end of a pseudo terminal pair, returned along with the other end
+    #[cfg(unix)]
+    fn pty_connection() -> (UsbConnection, serialport::TTYPort) {
+        let (serial, device) = serialport::TTYPort::pair().unwrap();
+        let usb = UsbConnection {
+            serial: Box::new(serial),
+            settings: serialport::new("/dev/ttyACM0", 115200),
+            device_id: None,
+            auto_reopen: false,
+            inter_command_delay: Duration::ZERO,
+            usb_serial_number: None,
+        };
+        (usb, device)
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn transport_kind_is_usb() {
+        let (usb, _device) = pty_connection();
+        assert_eq!(Kwr103::from(usb).transport_kind(), TransportKind::Usb);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn set_read_timeout_updates_port_and_settings() {
+        let (mut usb, _device) = pty_connection();
+        usb.set_read_timeout(Duration::from_millis(500)).unwrap();
+        assert_eq!(usb.read_timeout(), Some(Duration::from_millis(500)));
+        assert_eq!(
+            usb.settings,
+            serialport::new("/dev/ttyACM0", 115200).timeout(Duration::from_millis(500))
+        );
+    }
+
+    #[test]
+    fn probe_detects_garbage_response() {
+        let mut mock = MockTransport::default();
+        mock.respond(b"\xf8\x80\x1c\xfe");
+        let result = probe_baud_rate(&mut mock, DeviceId::Id(1));
+        assert!(result.is_err_and(|e| e.to_string().contains("baud rate mismatch")));
+        assert_eq!(mock.sent(), vec![b"VSET01?\n".to_vec()]);
+    }
+
+    #[test]
+    fn probe_accepts_valid_response() {
+        let mut mock = MockTransport::default();
+        mock.respond(b"12.000\n");
+        assert!(probe_baud_rate(&mut mock, DeviceId::None).is_ok());
+    }
 }